@@ -1,5 +1,8 @@
+use crate::{CliVersion, Error, SessionId};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -28,9 +31,316 @@ pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<usize>,
     
-    /// Timeout in seconds for Claude CLI execution (default: 30s)
+    /// Total deadline in seconds for Claude CLI execution (default: 30s)
+    /// 
+    /// `None`, which is also what a serialized config without this field
+    /// gets, means no deadline. Agentic runs can take much longer; raise or
+    /// drop this and rely on `idle_timeout_secs` to catch hung processes.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timeout_secs: Option<u64>,
+    
+    /// Kill the CLI when no stdout line arrives for this many seconds (default: off)
+    /// 
+    /// Only applies to [`StreamFormat::StreamJson`] and streams, the only
+    /// output that arrives while the run is in progress.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_secs: Option<u64>,
+    
+    /// CLI session to continue, passed as `--resume <id>`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resume_session: Option<SessionId>,
+    
+    /// Working directory for the Claude process (default: inherited)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<PathBuf>,
+    
+    /// Extra directories Claude may access, passed as `--add-dir`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add_dirs: Vec<PathBuf>,
+    
+    /// Environment variables set for the Claude process
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    
+    /// Environment variables removed from the inherited environment
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_remove: Vec<String>,
+    
+    /// Private CLI config directory, passed as `CLAUDE_CONFIG_DIR`
+    /// 
+    /// The CLI keeps credentials, settings and session history there, so
+    /// clients with different config homes share none of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_home: Option<PathBuf>,
+    
+    /// Directory copied to `config_home` when that does not exist yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_home_template: Option<PathBuf>,
+    
+    /// Start the Claude process from an empty environment
+    /// 
+    /// Only `env` is passed on. The CLI usually still needs `PATH` (to find
+    /// `node`) and `HOME` (for its credentials), so set those explicitly.
+    #[serde(default)]
+    pub env_clear: bool,
+    
+    /// Path to the `claude` executable
+    /// 
+    /// When unset, `CLAUDE_BIN` is consulted, then `PATH`, then common
+    /// install locations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claude_binary: Option<PathBuf>,
+    
+    /// Oldest CLI version this client is meant to run against
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_cli_version: Option<CliVersion>,
+    
+    /// What to do when the CLI is older than `min_cli_version`
+    #[serde(default)]
+    pub version_policy: VersionPolicy,
+    
+    /// Probe `claude --help` once per client and reject settings the CLI
+    /// cannot honor before spawning it (default: true)
+    #[serde(default = "default_true")]
+    pub check_capabilities: bool,
+    
+    /// How the query reaches the CLI: as the last argument or over stdin
+    #[serde(default)]
+    pub prompt_delivery: PromptDelivery,
+    
+    /// Retries for transient failures (default: none)
+    #[serde(default)]
+    pub retry_policy: RetryPolicy,
+    
+    /// Caps on CLI output held in memory (default: unlimited)
+    #[serde(default)]
+    pub output_limits: OutputLimits,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Reaction to a CLI older than [`Config::min_cli_version`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionPolicy {
+    /// Log a warning and run anyway
+    #[default]
+    Warn,
+    /// Fail with [`Error::UnsupportedCliVersion`](crate::Error::UnsupportedCliVersion)
+    Require,
+}
+
+/// When and how often to retry a query that failed transiently
+/// 
+/// Only errors for which [`Error::is_retryable`](crate::Error::is_retryable)
+/// holds are retried, plus timeouts if `retry_timeouts` is set. The delay before attempt `n + 1` is
+/// `initial_backoff_ms * multiplier^(n - 1)`, capped at `max_backoff_ms`,
+/// with up to `jitter` of it randomized away so that concurrent clients
+/// spread out. A retry-after hint from the API replaces a shorter delay; an
+/// error whose hint is longer than `max_backoff_ms` is not retried.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total attempts including the first; 1 disables retries
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub multiplier: f64,
+    /// Fraction of each delay that is randomized, from 0.0 to 1.0
+    pub jitter: f64,
+    /// Wait at least as long as a rate limit's retry-after hint asks
+    pub respect_retry_after: bool,
+    /// Also retry runs that timed out; their tools run again on every attempt
+    pub retry_timeouts: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            multiplier: 2.0,
+            jitter: 0.5,
+            respect_retry_after: true,
+            retry_timeouts: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        Self::default()
+    }
+    
+    /// Retry up to `max_attempts` attempts in total with the default backoff
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            ..Self::default()
+        }
+    }
+    
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff_ms = initial.as_millis() as u64;
+        self.max_backoff_ms = max.as_millis() as u64;
+        self
+    }
+    
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+    
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+    
+    pub fn respect_retry_after(mut self, respect: bool) -> Self {
+        self.respect_retry_after = respect;
+        self
+    }
+    
+    /// Opt in to retrying timed-out runs, which repeats any tool use they did
+    pub fn retry_timeouts(mut self, retry: bool) -> Self {
+        self.retry_timeouts = retry;
+        self
+    }
+    
+    /// Whether a failed `attempt` (counting from 1) should be followed by another
+    pub fn should_retry(&self, attempt: u32, error: &Error) -> bool {
+        let max_backoff = Duration::from_millis(self.max_backoff_ms);
+        attempt < self.max_attempts
+            && (error.is_retryable() || (self.retry_timeouts && error.is_timeout()))
+            && !matches!(self.retry_after(error), Some(wait) if wait > max_backoff)
+    }
+    
+    /// How long to wait after failed `attempt` (counting from 1) before the next one
+    pub fn delay(&self, attempt: u32, error: &Error) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(63) as i32;
+        let backoff = (self.initial_backoff_ms as f64 * self.multiplier.powi(exponent))
+            .min(self.max_backoff_ms as f64);
+        let jitter = self.jitter.clamp(0.0, 1.0) * random_fraction();
+        let delay = Duration::from_millis((backoff * (1.0 - jitter)) as u64);
+        
+        match self.retry_after(error) {
+            Some(wait) => delay.max(wait.min(Duration::from_millis(self.max_backoff_ms))),
+            None => delay,
+        }
+    }
+    
+    /// The API's retry-after hint for `error`, if this policy honours it
+    fn retry_after(&self, error: &Error) -> Option<Duration> {
+        match error.cause() {
+            Error::RateLimited { retry_after } if self.respect_retry_after => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// A random number in `[0, 1)`, seeded per call by the standard library
+fn random_fraction() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    
+    let bits = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// How much CLI output a query may hold in memory
+/// 
+/// `send`/`send_full` buffer stdout until the process exits, so there
+/// `max_stdout_bytes` caps the whole output. Streams hand each line on as it
+/// arrives and only cap the length of a single line, and the recent output
+/// they keep for the partial messages of a timeout. `max_raw_messages` caps
+/// the stream-json messages kept in
+/// [`ClaudeResponse::raw_json`](crate::ClaudeResponse::raw_json) and in a
+/// stream's timeout error.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct OutputLimits {
+    pub max_stdout_bytes: Option<usize>,
+    pub max_stderr_bytes: Option<usize>,
+    pub max_raw_messages: Option<usize>,
+    pub on_overflow: OverflowPolicy,
+}
+
+impl OutputLimits {
+    /// No limits
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+    
+    pub fn max_stdout_bytes(mut self, bytes: usize) -> Self {
+        self.max_stdout_bytes = Some(bytes);
+        self
+    }
+    
+    pub fn max_stderr_bytes(mut self, bytes: usize) -> Self {
+        self.max_stderr_bytes = Some(bytes);
+        self
+    }
+    
+    pub fn max_raw_messages(mut self, messages: usize) -> Self {
+        self.max_raw_messages = Some(messages);
+        self
+    }
+    
+    pub fn on_overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.on_overflow = policy;
+        self
+    }
+}
+
+/// What happens when output passes one of its [`OutputLimits`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// Kill the process and fail with
+    /// [`Error::OutputLimitExceeded`](crate::Error::OutputLimitExceeded)
+    #[default]
+    Error,
+    /// Keep going and drop what does not fit
+    /// 
+    /// Text output keeps its beginning and stderr its end. Stream-json output
+    /// keeps the whole lines that fit, plus the last line (normally the
+    /// result) as long as it is within the limit by itself. Past
+    /// `max_raw_messages`, the oldest messages are dropped. JSON output is a
+    /// single document that cannot be cut, so it always fails.
+    Truncate,
+}
+
+/// How a one-shot query is handed to `claude -p`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptDelivery {
+    /// Pipe prompts longer than [`PromptDelivery::STDIN_THRESHOLD`] bytes
+    /// over stdin, pass shorter ones as an argument
+    #[default]
+    Auto,
+    /// Always pass the prompt as the last command-line argument
+    Argument,
+    /// Always pipe the prompt over stdin, keeping it out of `ps` output
+    Stdin,
+}
+
+impl PromptDelivery {
+    /// Prompt size above which [`PromptDelivery::Auto`] switches to stdin
+    /// 
+    /// Kept well below Linux's 128 KiB limit on a single argument.
+    pub const STDIN_THRESHOLD: usize = 32 * 1024;
+    
+    /// Whether `prompt` should be written to stdin
+    pub fn uses_stdin(self, prompt: &str) -> bool {
+        match self {
+            PromptDelivery::Auto => prompt.len() > Self::STDIN_THRESHOLD,
+            PromptDelivery::Argument => false,
+            PromptDelivery::Stdin => true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
@@ -54,6 +364,22 @@ impl Default for Config {
             verbose: false,
             max_tokens: None,
             timeout_secs: Some(30), // Default 30 second timeout
+            idle_timeout_secs: None,
+            resume_session: None,
+            working_dir: None,
+            add_dirs: Vec::new(),
+            env: BTreeMap::new(),
+            env_remove: Vec::new(),
+            env_clear: false,
+            config_home: None,
+            config_home_template: None,
+            claude_binary: None,
+            min_cli_version: None,
+            version_policy: VersionPolicy::default(),
+            check_capabilities: true,
+            prompt_delivery: PromptDelivery::default(),
+            retry_policy: RetryPolicy::default(),
+            output_limits: OutputLimits::default(),
         }
     }
 }
@@ -62,12 +388,107 @@ impl Config {
     pub fn builder() -> ConfigBuilder {
         ConfigBuilder::new()
     }
+    
+    /// Return a copy of this configuration with the overrides applied on top
+    pub fn with_overrides(&self, overrides: &ConfigOverrides) -> Config {
+        let mut config = self.clone();
+        overrides.apply(&mut config);
+        config
+    }
+}
+
+/// Per-query settings layered over a client's [`Config`]
+/// 
+/// Every field left as `None` keeps the client's value. `add_dirs`, `env` and
+/// `env_remove` add to the client's values instead of replacing them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConfigOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_format: Option<StreamFormat>,
+    
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_tools: Option<Vec<String>>,
+    
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_secs: Option<u64>,
+    
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<PathBuf>,
+    
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add_dirs: Vec<PathBuf>,
+    
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_remove: Vec<String>,
+    
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env_clear: Option<bool>,
+}
+
+impl ConfigOverrides {
+    /// Write every set override into `config`
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(format) = self.stream_format {
+            config.stream_format = format;
+        }
+        if let Some(model) = &self.model {
+            config.model = Some(model.clone());
+        }
+        if let Some(prompt) = &self.system_prompt {
+            config.system_prompt = Some(prompt.clone());
+        }
+        if let Some(tools) = &self.allowed_tools {
+            config.allowed_tools = Some(tools.clone());
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            config.max_tokens = Some(max_tokens);
+        }
+        if let Some(timeout_secs) = self.timeout_secs {
+            config.timeout_secs = Some(timeout_secs);
+        }
+        if let Some(idle_secs) = self.idle_timeout_secs {
+            config.idle_timeout_secs = Some(idle_secs);
+        }
+        if let Some(dir) = &self.working_dir {
+            config.working_dir = Some(dir.clone());
+        }
+        config.add_dirs.extend(self.add_dirs.iter().cloned());
+        for key in &self.env_remove {
+            config.env.remove(key);
+            config.env_remove.push(key.clone());
+        }
+        config.env.extend(self.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        if let Some(clear) = self.env_clear {
+            config.env_clear = clear;
+        }
+    }
 }
 
 pub struct ConfigBuilder {
     config: Config,
 }
 
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ConfigBuilder {
     pub fn new() -> Self {
         Self {
@@ -116,6 +537,88 @@ impl ConfigBuilder {
         self
     }
     
+    /// Let queries run as long as they take; pair with an idle timeout
+    pub fn no_timeout(mut self) -> Self {
+        self.config.timeout_secs = None;
+        self
+    }
+    
+    pub fn idle_timeout_secs(mut self, idle_secs: u64) -> Self {
+        self.config.idle_timeout_secs = Some(idle_secs);
+        self
+    }
+    
+    pub fn working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.working_dir = Some(dir.into());
+        self
+    }
+    
+    pub fn add_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.add_dirs.push(dir.into());
+        self
+    }
+    
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.env.insert(key.into(), value.into());
+        self
+    }
+    
+    pub fn env_remove(mut self, key: impl Into<String>) -> Self {
+        self.config.env_remove.push(key.into());
+        self
+    }
+    
+    pub fn env_clear(mut self, clear: bool) -> Self {
+        self.config.env_clear = clear;
+        self
+    }
+    
+    pub fn config_home(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.config_home = Some(dir.into());
+        self
+    }
+    
+    pub fn config_home_template(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.config_home_template = Some(dir.into());
+        self
+    }
+    
+    pub fn claude_binary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.claude_binary = Some(path.into());
+        self
+    }
+    
+    pub fn min_cli_version(mut self, version: CliVersion, policy: VersionPolicy) -> Self {
+        self.config.min_cli_version = Some(version);
+        self.config.version_policy = policy;
+        self
+    }
+    
+    pub fn check_capabilities(mut self, check: bool) -> Self {
+        self.config.check_capabilities = check;
+        self
+    }
+    
+    pub fn resume_session(mut self, session_id: SessionId) -> Self {
+        self.config.resume_session = Some(session_id);
+        self
+    }
+    
+    pub fn prompt_delivery(mut self, delivery: PromptDelivery) -> Self {
+        self.config.prompt_delivery = delivery;
+        self
+    }
+    
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.config.retry_policy = policy;
+        self
+    }
+    
+    pub fn output_limits(mut self, limits: OutputLimits) -> Self {
+        self.config.output_limits = limits;
+        self
+    }
+    
     pub fn build(self) -> Config {
         self.config
     }
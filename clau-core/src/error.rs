@@ -1,9 +1,23 @@
+use crate::{CliVersion, Message, ResultMessage};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("Claude Code not found in PATH")]
-    BinaryNotFound,
+    #[error("Claude Code not found (searched: {})", display_paths(.searched))]
+    BinaryNotFound {
+        /// Every location that was checked, in order
+        searched: Vec<PathBuf>,
+    },
+    
+    #[error("Claude Code {found} is older than the required {required}")]
+    UnsupportedCliVersion {
+        found: CliVersion,
+        required: CliVersion,
+    },
     
     #[error("Session {0} not found")]
     SessionNotFound(String),
@@ -23,6 +37,9 @@ pub enum Error {
     #[error("Operation timed out after {0}s")]
     Timeout(u64),
     
+    #[error("Operation cancelled")]
+    Cancelled,
+    
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
     
@@ -32,8 +49,345 @@ pub enum Error {
     #[error("Process error: {0}")]
     ProcessError(String),
     
+    #[error("{0}")]
+    ProcessFailed(Box<ProcessFailure>),
+    
+    #[error("{0}")]
+    QueryTimedOut(Box<QueryTimeout>),
+    
+    #[error("Rate limited by the API{}", display_retry_after(.retry_after))]
+    RateLimited {
+        /// How long the API asked us to wait, when it said
+        retry_after: Option<Duration>,
+    },
+    
+    #[error("Authentication failed: {0}")]
+    AuthenticationFailed(String),
+    
+    #[error("Prompt exceeds the model's context window: {0}")]
+    ContextTooLong(String),
+    
+    #[error("The API is overloaded: {0}")]
+    Overloaded(String),
+    
+    #[error("Reached the maximum number of turns ({})", .0.num_turns)]
+    MaxTurnsReached(Box<RunFailure>),
+    
+    #[error("Claude failed during execution: {0}")]
+    ExecutionError(Box<RunFailure>),
+    
+    /// A failed run whose result names a known API failure, such as
+    /// [`Error::Overloaded`]
+    #[error("{cause}")]
+    RunFailed {
+        cause: Box<Error>,
+        failure: Box<RunFailure>,
+    },
+    
+    #[error("Claude output went over the limit of {limit} {kind}")]
+    OutputLimitExceeded {
+        kind: OutputKind,
+        limit: usize,
+    },
+    
     #[error("Stream closed unexpectedly")]
     StreamClosed,
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
\ No newline at end of file
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Whether the same request may succeed if tried again later
+    /// 
+    /// True for rate limiting and overload, which the API rejects before the
+    /// run starts. Timeouts are not included: the run may already have used
+    /// tools, which a new attempt would use again. Configuration,
+    /// authentication and context-size errors will fail the same way again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.cause(), Error::RateLimited { .. } | Error::Overloaded(_))
+    }
+    
+    /// Whether the run was killed for exceeding its total or idle deadline
+    pub fn is_timeout(&self) -> bool {
+        matches!(self.cause(), Error::Timeout(_) | Error::QueryTimedOut(_))
+    }
+    
+    /// The underlying error, looking through [`Error::RunFailed`]
+    pub fn cause(&self) -> &Error {
+        match self {
+            Error::RunFailed { cause, .. } => cause,
+            other => other,
+        }
+    }
+    
+    /// The failed run this error comes from, if it came from an error result
+    pub fn run_failure(&self) -> Option<&RunFailure> {
+        match self {
+            Error::MaxTurnsReached(failure)
+            | Error::ExecutionError(failure)
+            | Error::RunFailed { failure, .. } => Some(failure),
+            _ => None,
+        }
+    }
+    
+    /// Recognize a known API failure in CLI output such as stderr or an error result
+    pub fn classify(output: &str) -> Option<Error> {
+        let lower = output.to_lowercase();
+        let message = output.trim().to_string();
+        let mentions = |patterns: &[&str]| patterns.iter().any(|p| lower.contains(p));
+        
+        if mentions(&["rate limit", "rate_limit", "too many requests", "usage limit reached"]) {
+            return Some(Error::RateLimited { retry_after: parse_retry_after(&lower) });
+        }
+        if mentions(&["overloaded"]) {
+            return Some(Error::Overloaded(message));
+        }
+        if mentions(&[
+            "invalid api key",
+            "authentication_error",
+            "authentication failed",
+            "not logged in",
+            "please run /login",
+            "oauth token has expired",
+        ]) {
+            return Some(Error::AuthenticationFailed(message));
+        }
+        if mentions(&["prompt is too long", "context window", "context_length", "exceed context limit"]) {
+            return Some(Error::ContextTooLong(message));
+        }
+        None
+    }
+    
+    /// Error for a `claude` process that exited unsuccessfully
+    /// 
+    /// Known failures in stderr get their own variant; anything else becomes
+    /// [`Error::ProcessFailed`] carrying the full failure context.
+    pub fn process_failed(failure: ProcessFailure) -> Error {
+        Error::classify(&failure.stderr)
+            .unwrap_or_else(|| Error::ProcessFailed(Box::new(failure)))
+    }
+    
+    /// Error reported by a CLI `result` message, if it reports one
+    /// 
+    /// Looks at the `subtype` (`error_max_turns`, `error_during_execution`),
+    /// the `is_error` flag and the result text. Successful results yield `None`.
+    pub fn from_result(result: &Value) -> Option<Error> {
+        ResultMessage::deserialize(result).ok()?.failure().map(Error::run_failed)
+    }
+    
+    /// Error for a run the CLI reported as failed
+    /// 
+    /// Runs that hit the turn limit become [`Error::MaxTurnsReached`]. Known
+    /// API failures in the result text become [`Error::RunFailed`] around the
+    /// classified cause, and anything else [`Error::ExecutionError`]. All of
+    /// them keep the turn count, cost and session of the run.
+    pub fn run_failed(failure: RunFailure) -> Error {
+        if failure.subtype == "error_max_turns" {
+            return Error::MaxTurnsReached(Box::new(failure));
+        }
+        match Error::classify(&failure.result) {
+            Some(cause) => Error::RunFailed { cause: Box::new(cause), failure: Box::new(failure) },
+            None => Error::ExecutionError(Box::new(failure)),
+        }
+    }
+}
+
+/// How and why a `claude` process failed
+/// 
+/// `stderr` and `stdout` keep only the last [`ProcessFailure::OUTPUT_LIMIT`]
+/// bytes of each stream. The command line never contains the prompt or the
+/// system prompt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessFailure {
+    /// Exit code, if the process exited on its own
+    pub exit_code: Option<i32>,
+    /// Signal that terminated the process (Unix only)
+    pub signal: Option<i32>,
+    /// The command line that was run, with prompt contents redacted
+    pub command: String,
+    /// Tail of stderr
+    pub stderr: String,
+    /// Tail of whatever stdout was produced before the failure
+    pub stdout: String,
+}
+
+impl ProcessFailure {
+    /// Bytes kept from the end of stderr and stdout
+    pub const OUTPUT_LIMIT: usize = 4096;
+    
+    pub fn new(command: impl Into<String>, exit_code: Option<i32>, signal: Option<i32>, stderr: &str, stdout: &str) -> Self {
+        Self {
+            exit_code,
+            signal,
+            command: command.into(),
+            stderr: tail(stderr, Self::OUTPUT_LIMIT).to_string(),
+            stdout: tail(stdout, Self::OUTPUT_LIMIT).to_string(),
+        }
+    }
+    
+    /// Whether the process was terminated by a signal rather than exiting
+    pub fn was_signaled(&self) -> bool {
+        self.signal.is_some()
+    }
+}
+
+impl std::fmt::Display for ProcessFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Claude command failed (")?;
+        match (self.exit_code, self.signal) {
+            (_, Some(signal)) => write!(f, "killed by signal {}", signal)?,
+            (Some(code), None) => write!(f, "exit status {}", code)?,
+            (None, None) => write!(f, "unknown exit status")?,
+        }
+        write!(f, "): {}", self.command)?;
+        
+        let stderr = self.stderr.trim();
+        if !stderr.is_empty() {
+            write!(f, ": {}", stderr)?;
+        }
+        Ok(())
+    }
+}
+
+/// The last `limit` bytes of `text`, cut at a character boundary
+fn tail(text: &str, limit: usize) -> &str {
+    let mut start = text.len().saturating_sub(limit);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    &text[start..]
+}
+
+/// A run that ended with an error `result` message
+/// 
+/// The CLI still reports what the run used before it stopped, so the
+/// session can be resumed and its cost accounted for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunFailure {
+    /// Error subtype such as `error_max_turns` or `error_during_execution`
+    pub subtype: String,
+    pub num_turns: u32,
+    pub cost_usd: Option<f64>,
+    pub session_id: String,
+    /// Whatever text the result carried; not an answer to the prompt
+    pub result: String,
+}
+
+impl RunFailure {
+    /// The failure a result with these fields reports, or `None` for a
+    /// successful run
+    /// 
+    /// A result is a failure if `is_error` is set or its subtype is an error.
+    pub(crate) fn detect(
+        is_error: bool,
+        subtype: &str,
+        num_turns: u32,
+        cost_usd: Option<f64>,
+        session_id: &str,
+        result: &str,
+    ) -> Option<Self> {
+        (is_error || subtype.starts_with("error")).then(|| Self {
+            subtype: subtype.to_string(),
+            num_turns,
+            cost_usd,
+            session_id: session_id.to_string(),
+            result: result.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for RunFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.result.trim() {
+            "" => f.write_str(&self.subtype),
+            text => f.write_str(text),
+        }
+    }
+}
+
+/// Which limit a query ran into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutKind {
+    /// [`Config::timeout_secs`](crate::Config::timeout_secs), the total deadline
+    Total,
+    /// [`Config::idle_timeout_secs`](crate::Config::idle_timeout_secs), the
+    /// longest allowed gap between two lines of output
+    Idle,
+}
+
+/// Which part of the output went over its [`OutputLimits`](crate::OutputLimits)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    Stdout,
+    Stderr,
+    RawMessages,
+}
+
+impl std::fmt::Display for OutputKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OutputKind::Stdout => "stdout bytes",
+            OutputKind::Stderr => "stderr bytes",
+            OutputKind::RawMessages => "raw messages",
+        })
+    }
+}
+
+/// A query killed for taking too long, with what it produced until then
+#[derive(Debug, Clone)]
+pub struct QueryTimeout {
+    pub kind: TimeoutKind,
+    /// The limit that expired, in seconds
+    pub secs: u64,
+    /// Messages received before the process was killed
+    /// 
+    /// Stream-json output yields one message per line; text output yields a
+    /// single assistant message with the text so far.
+    pub partial: Vec<Message>,
+}
+
+impl std::fmt::Display for QueryTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            TimeoutKind::Total => write!(f, "Query exceeded its {}s deadline", self.secs)?,
+            TimeoutKind::Idle => write!(f, "No output from Claude for {}s", self.secs)?,
+        }
+        write!(f, " ({} partial messages received)", self.partial.len())
+    }
+}
+
+/// Pull a wait time out of messages like "retry after 30 seconds" or the
+/// CLI's "usage limit reached|<unix timestamp>"
+fn parse_retry_after(lower: &str) -> Option<Duration> {
+    for marker in ["retry-after:", "retry after", "try again in"] {
+        if let Some(index) = lower.find(marker) {
+            let rest = lower[index + marker.len()..].trim_start();
+            let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+            if let Ok(secs) = digits.parse() {
+                return Some(Duration::from_secs(secs));
+            }
+        }
+    }
+    
+    let (_, reset) = lower.split_once("usage limit reached|")?;
+    let digits: String = reset.chars().take_while(|c| c.is_ascii_digit()).collect();
+    let reset = UNIX_EPOCH + Duration::from_secs(digits.parse().ok()?);
+    Some(reset.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+fn display_retry_after(retry_after: &Option<Duration>) -> String {
+    match retry_after {
+        Some(wait) => format!("; retry after {}s", wait.as_secs()),
+        None => String::new(),
+    }
+}
+
+fn display_paths(paths: &[PathBuf]) -> String {
+    if paths.is_empty() {
+        return "nothing".to_string();
+    }
+    paths.iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
\ No newline at end of file
@@ -4,8 +4,12 @@ pub mod session;
 pub mod config;
 pub mod types;
 
-pub use error::{Error, Result};
-pub use message::{Message, MessageType, MessageMeta};
+pub use error::{Error, OutputKind, ProcessFailure, QueryTimeout, Result, RunFailure, TimeoutKind};
+pub use message::{
+    Message, MessageType, MessageMeta,
+    CliMessage, SystemMessage, AssistantMessage, UserMessage, ResultMessage, ApiMessage, McpServerStatus,
+    ContentBlock, ToolResultContent,
+};
 pub use session::{Session, SessionId, SessionManager};
-pub use config::{Config, StreamFormat};
-pub use types::{ToolPermission, Cost, ClaudeCliResponse, ClaudeResponse, ResponseMetadata, TokenUsage};
\ No newline at end of file
+pub use config::{Config, ConfigOverrides, OutputLimits, OverflowPolicy, PromptDelivery, RetryPolicy, StreamFormat, VersionPolicy};
+pub use types::{ToolPermission, Cost, ClaudeCliResponse, ClaudeResponse, ResponseMetadata, ResultSchema, TokenUsage, CliVersion};
\ No newline at end of file
@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use crate::error::RunFailure;
+use crate::types::{ResultSchema, TokenUsage};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
 use std::time::SystemTime;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -20,13 +23,9 @@ pub struct MessageMeta {
     pub cost_usd: Option<f64>,
     pub duration_ms: Option<u64>,
     pub tokens_used: Option<TokenUsage>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TokenUsage {
-    pub input: u64,
-    pub output: u64,
-    pub total: u64,
+    /// Fields of the message not modelled by its variant
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,6 +49,9 @@ pub enum Message {
         #[serde(flatten)]
         meta: MessageMeta,
         stats: ConversationStats,
+        /// Set when the run ended in an error rather than an answer
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        failure: Option<RunFailure>,
     },
     System {
         content: String,
@@ -59,11 +61,18 @@ pub enum Message {
     Tool {
         name: String,
         parameters: serde_json::Value,
+        /// Id the matching [`Message::ToolResult`] refers to
+        #[serde(default)]
+        tool_use_id: String,
         #[serde(flatten)]
         meta: MessageMeta,
     },
     ToolResult {
+        /// Empty when built from CLI output, whose tool results only carry
+        /// the id; look the name up from the [`Message::Tool`] with that id
         tool_name: String,
+        #[serde(default)]
+        tool_use_id: String,
         result: serde_json::Value,
         #[serde(flatten)]
         meta: MessageMeta,
@@ -102,4 +111,334 @@ impl Message {
             Message::ToolResult { meta, .. } => meta,
         }
     }
-}
\ No newline at end of file
+}
+
+/// One line of `--output-format stream-json` output, exactly as the CLI writes it
+/// 
+/// [`Message`] is the SDK's simplified view; this keeps every content block,
+/// tool id and usage figure. Message types this version does not know end up
+/// in [`CliMessage::Other`] rather than failing to parse.
+/// 
+/// Fields that are not modelled land in the `extra` map of the struct or
+/// content block they belong to and are written back out on serialization,
+/// so a message round-trips unchanged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CliMessage {
+    System(SystemMessage),
+    Assistant(AssistantMessage),
+    User(UserMessage),
+    Result(ResultMessage),
+    /// Any other message, kept as the CLI sent it
+    #[serde(untagged)]
+    Other(Value),
+}
+
+/// `system` messages; the `init` subtype opens every run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemMessage {
+    pub subtype: String,
+    #[serde(default)]
+    pub session_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Tools available to the run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mcp_servers: Vec<McpServerStatus>,
+    #[serde(rename = "permissionMode", default, skip_serializing_if = "Option::is_none")]
+    pub permission_mode: Option<String>,
+    #[serde(rename = "apiKeySource", default, skip_serializing_if = "Option::is_none")]
+    pub api_key_source: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// An MCP server listed in the `init` message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpServerStatus {
+    pub name: String,
+    pub status: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A turn of the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssistantMessage {
+    pub message: ApiMessage,
+    #[serde(default)]
+    pub session_id: String,
+    /// The tool call this message belongs to, for messages of a subagent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_tool_use_id: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Input to the model, in practice tool results
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserMessage {
+    pub message: ApiMessage,
+    #[serde(default)]
+    pub session_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_tool_use_id: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The Messages API message wrapped by assistant and user messages
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiMessage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Content blocks; content given as a plain string becomes one text block
+    #[serde(deserialize_with = "content_blocks")]
+    pub content: Vec<ContentBlock>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ApiMessage {
+    /// The text blocks, concatenated
+    pub fn text(&self) -> String {
+        self.content.iter().filter_map(ContentBlock::as_text).collect()
+    }
+    
+    pub fn tool_uses(&self) -> impl Iterator<Item = &ContentBlock> {
+        self.content.iter().filter(|block| matches!(block, ContentBlock::ToolUse { .. }))
+    }
+}
+
+/// The final message of a run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResultMessage {
+    /// `success` or an error such as `error_max_turns`
+    #[serde(default)]
+    pub subtype: String,
+    #[serde(default)]
+    pub is_error: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_api_ms: Option<u64>,
+    #[serde(default)]
+    pub num_turns: u32,
+    /// The final answer; error results usually have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    #[serde(default)]
+    pub session_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_cost_usd: Option<f64>,
+    /// Older CLIs' name for the cost
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ResultMessage {
+    /// Cost of the run in USD, under whichever name the CLI reported it
+    pub fn cost(&self) -> Option<f64> {
+        ResultSchema::read_cost(|field| match field {
+            "total_cost_usd" => self.total_cost_usd,
+            "cost_usd" => self.cost_usd,
+            other => self.extra.get(other).and_then(Value::as_f64),
+        })
+        .1
+    }
+    
+    /// The failure this result reports, if the run did not succeed
+    pub fn failure(&self) -> Option<RunFailure> {
+        let result = self.result.as_deref().unwrap_or_default();
+        RunFailure::detect(self.is_error, &self.subtype, self.num_turns, self.cost(), &self.session_id, result)
+    }
+}
+
+/// A block of message content
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    ToolResult {
+        tool_use_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content: Option<ToolResultContent>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        is_error: Option<bool>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Thinking {
+        thinking: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    /// Any other block, such as images, kept as the CLI sent it
+    #[serde(untagged)]
+    Other(Value),
+}
+
+impl ContentBlock {
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            ContentBlock::Text { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// What a tool returned: a plain string or content blocks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ToolResultContent {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+impl ToolResultContent {
+    /// The result as text, joining text blocks
+    pub fn text(&self) -> String {
+        match self {
+            ToolResultContent::Text(text) => text.clone(),
+            ToolResultContent::Blocks(blocks) => blocks.iter().filter_map(ContentBlock::as_text).collect(),
+        }
+    }
+}
+
+fn content_blocks<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<ContentBlock>, D::Error> {
+    Ok(match ToolResultContent::deserialize(deserializer)? {
+        ToolResultContent::Text(text) => vec![ContentBlock::Text { text, extra: Map::new() }],
+        ToolResultContent::Blocks(blocks) => blocks,
+    })
+}
+
+impl CliMessage {
+    /// The CLI session this message belongs to, if it says
+    pub fn session_id(&self) -> Option<&str> {
+        let id = match self {
+            CliMessage::System(message) => &message.session_id,
+            CliMessage::Assistant(message) => &message.session_id,
+            CliMessage::User(message) => &message.session_id,
+            CliMessage::Result(message) => &message.session_id,
+            CliMessage::Other(value) => return value.get("session_id").and_then(Value::as_str),
+        };
+        Some(id.as_str()).filter(|id| !id.is_empty())
+    }
+    
+    /// The SDK's simplified view of this message
+    /// 
+    /// Assistant messages with only tool calls become [`Message::Tool`] for
+    /// the first call; user messages with tool results become
+    /// [`Message::ToolResult`] for the first result. Unknown messages have no
+    /// simplified view. Fields the message does not model are carried over
+    /// into [`MessageMeta::extra`].
+    pub fn to_message(&self) -> Option<Message> {
+        let extra = match self {
+            CliMessage::System(message) => &message.extra,
+            CliMessage::Assistant(message) => &message.extra,
+            CliMessage::User(message) => &message.extra,
+            CliMessage::Result(message) => &message.extra,
+            CliMessage::Other(_) => return None,
+        };
+        let meta = MessageMeta {
+            session_id: self.session_id().unwrap_or_default().to_string(),
+            timestamp: Some(SystemTime::now()),
+            cost_usd: None,
+            duration_ms: None,
+            tokens_used: None,
+            // Keys meta already models would be written out twice
+            extra: extra
+                .iter()
+                .filter(|(key, _)| !matches!(key.as_str(), "timestamp" | "cost_usd" | "duration_ms" | "tokens_used"))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        };
+        
+        match self {
+            CliMessage::System(system) if system.subtype == "init" => Some(Message::Init { meta }),
+            CliMessage::System(system) => Some(Message::System {
+                content: system.subtype.clone(),
+                meta,
+            }),
+            CliMessage::Assistant(assistant) => {
+                let meta = MessageMeta {
+                    tokens_used: assistant.message.usage.clone(),
+                    ..meta
+                };
+                let text = assistant.message.text();
+                match assistant.message.tool_uses().next() {
+                    Some(ContentBlock::ToolUse { id, name, input, .. }) if text.is_empty() => Some(Message::Tool {
+                        name: name.clone(),
+                        parameters: input.clone(),
+                        tool_use_id: id.clone(),
+                        meta,
+                    }),
+                    _ => Some(Message::Assistant { content: text, meta }),
+                }
+            }
+            CliMessage::User(user) => {
+                let tool_result = user.message.content.iter().find_map(|block| match block {
+                    ContentBlock::ToolResult { tool_use_id, content, .. } => Some((tool_use_id, content)),
+                    _ => None,
+                });
+                match tool_result {
+                    Some((tool_use_id, content)) => Some(Message::ToolResult {
+                        tool_name: String::new(),
+                        tool_use_id: tool_use_id.clone(),
+                        result: serde_json::to_value(content).unwrap_or_default(),
+                        meta,
+                    }),
+                    None => Some(Message::User {
+                        content: user.message.text(),
+                        meta,
+                    }),
+                }
+            }
+            CliMessage::Result(result) => {
+                let meta = MessageMeta {
+                    cost_usd: result.cost(),
+                    duration_ms: result.duration_ms,
+                    tokens_used: result.usage.clone(),
+                    ..meta
+                };
+                let stats = ConversationStats {
+                    total_messages: result.num_turns as u64,
+                    total_cost_usd: meta.cost_usd.unwrap_or(0.0),
+                    total_duration_ms: meta.duration_ms.unwrap_or(0),
+                    total_tokens: result.usage.clone().unwrap_or_default(),
+                };
+                Some(Message::Result { meta, stats, failure: result.failure() })
+            }
+            CliMessage::Other(_) => None,
+        }
+    }
+}
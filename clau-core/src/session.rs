@@ -103,6 +103,12 @@ pub struct SessionBuilder {
     manager: Option<SessionManager>,
 }
 
+impl Default for SessionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SessionBuilder {
     pub fn new() -> Self {
         let id = SessionId::new(uuid::Uuid::new_v4().to_string());
@@ -1,19 +1,112 @@
+use crate::{CliMessage, RunFailure};
 use serde::{Deserialize, Serialize};
 
-/// Response from claude CLI in JSON format (legacy single response)
+/// Response from claude CLI in JSON format
+/// 
+/// Fields the CLI has renamed or may leave out are optional, so drift in its
+/// JSON shape does not fail the query. The cost has been reported as
+/// `cost_usd` and `total_cost` and is now `total_cost_usd`; [`cost`](Self::cost)
+/// reads whichever is present and [`schema`](Self::schema) tells which shape
+/// was seen.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClaudeCliResponse {
-    #[serde(rename = "type")]
+    #[serde(rename = "type", default = "result_type")]
     pub response_type: String,
+    #[serde(default)]
     pub subtype: String,
-    pub cost_usd: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    #[serde(default)]
     pub is_error: bool,
+    #[serde(default)]
     pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub duration_api_ms: Option<u64>,
+    #[serde(default)]
     pub num_turns: u32,
+    /// The final answer; error results may have none
+    #[serde(default)]
     pub result: String,
-    pub total_cost: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_cost: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_cost_usd: Option<f64>,
+    #[serde(default)]
     pub session_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+    /// Fields not modelled above, written back out on serialization
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+fn result_type() -> String {
+    "result".to_string()
+}
+
+impl ClaudeCliResponse {
+    /// The failure this response reports, if `is_error` is set or the subtype is an error
+    pub fn failure(&self) -> Option<RunFailure> {
+        RunFailure::detect(self.is_error, &self.subtype, self.num_turns, self.cost(), &self.session_id, &self.result)
+    }
+    
+    /// The error this response reports, if it reports a [`failure`](Self::failure)
+    pub fn error(&self) -> Option<crate::Error> {
+        self.failure().map(crate::Error::run_failed)
+    }
+    
+    /// Total cost in USD, under whichever name the CLI reported it
+    pub fn cost(&self) -> Option<f64> {
+        ResultSchema::read_cost(|field| self.cost_field(field)).1
+    }
+    
+    pub fn schema(&self) -> ResultSchema {
+        ResultSchema::read_cost(|field| self.cost_field(field)).0
+    }
+    
+    fn cost_field(&self, field: &str) -> Option<f64> {
+        match field {
+            "total_cost_usd" => self.total_cost_usd,
+            "total_cost" => self.total_cost,
+            "cost_usd" => self.cost_usd,
+            _ => None,
+        }
+    }
+}
+
+/// Generation of the CLI's result JSON, told apart by how it reports cost
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultSchema {
+    /// Cost in `cost_usd` and `total_cost`
+    V1,
+    /// Cost in `total_cost_usd`
+    V2,
+    /// No cost field this version knows; the rest was parsed with defaults
+    Unknown,
+}
+
+impl ResultSchema {
+    /// Names the CLI has reported a run's cost under, newest first
+    pub const COST_FIELDS: [(&'static str, ResultSchema); 3] = [
+        ("total_cost_usd", ResultSchema::V2),
+        ("total_cost", ResultSchema::V1),
+        ("cost_usd", ResultSchema::V1),
+    ];
+    
+    /// Schema of a raw result object
+    pub fn detect(result: &serde_json::Value) -> Self {
+        Self::read_cost(|field| result.get(field).and_then(serde_json::Value::as_f64)).0
+    }
+    
+    /// The first cost `field` yields among [`COST_FIELDS`](Self::COST_FIELDS),
+    /// with the schema its name belongs to
+    pub fn read_cost(field: impl Fn(&str) -> Option<f64>) -> (ResultSchema, Option<f64>) {
+        Self::COST_FIELDS
+            .iter()
+            .find_map(|(name, schema)| field(name).map(|cost| (*schema, Some(cost))))
+            .unwrap_or((ResultSchema::Unknown, None))
+    }
 }
 
 /// Comprehensive response that includes both parsed content and raw data
@@ -27,6 +120,10 @@ pub struct ClaudeResponse {
     
     /// Structured metadata when available
     pub metadata: Option<ResponseMetadata>,
+    
+    /// Attempts it took to get this response; more than 1 means it was retried
+    #[serde(default = "one")]
+    pub attempts: u32,
 }
 
 /// Structured metadata extracted from Claude CLI responses
@@ -37,15 +134,103 @@ pub struct ResponseMetadata {
     pub duration_ms: Option<u64>,
     pub tokens_used: Option<TokenUsage>,
     pub model: Option<String>,
+    /// Same as [`ClaudeResponse::attempts`]
+    #[serde(default = "one")]
+    pub attempts: u32,
+    /// Shape of the result JSON this was read from, if there was one
+    #[serde(default)]
+    pub schema: Option<ResultSchema>,
 }
 
-/// Token usage information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+fn one() -> u32 {
+    1
+}
+
+/// Token counts for one message or a whole run
+/// 
+/// The CLI reports usage on assistant messages and on the final result, in
+/// both JSON and stream-json output. Counts it leaves out are `None`. Usages
+/// add up, e.g. across the queries of a session; a sum keeps the first
+/// service tier it sees and drops `extra`, which has no meaningful total.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct TokenUsage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub output_tokens: Option<u64>,
+    /// Input tokens written to the prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cache_creation_input_tokens: Option<u64>,
+    /// Input tokens served from the prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cache_read_input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_tier: Option<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl TokenUsage {
+    pub fn new(input_tokens: u64, output_tokens: u64) -> Self {
+        Self {
+            input_tokens: Some(input_tokens),
+            output_tokens: Some(output_tokens),
+            ..Self::default()
+        }
+    }
+    
+    /// All input tokens, whether uncached, written to the cache or read from it
+    pub fn total_input(&self) -> u64 {
+        self.input_tokens.unwrap_or(0)
+            + self.cache_creation_input_tokens.unwrap_or(0)
+            + self.cache_read_input_tokens.unwrap_or(0)
+    }
+    
+    /// All input and output tokens
+    pub fn total(&self) -> u64 {
+        self.total_input() + self.output_tokens.unwrap_or(0)
+    }
+}
+
+impl std::ops::Add for TokenUsage {
+    type Output = TokenUsage;
+    
+    fn add(mut self, other: TokenUsage) -> TokenUsage {
+        self += other;
+        self
+    }
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: TokenUsage) {
+        fn sum(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+            match (a, b) {
+                (None, None) => None,
+                (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+            }
+        }
+        
+        self.input_tokens = sum(self.input_tokens, other.input_tokens);
+        self.output_tokens = sum(self.output_tokens, other.output_tokens);
+        self.cache_creation_input_tokens = sum(self.cache_creation_input_tokens, other.cache_creation_input_tokens);
+        self.cache_read_input_tokens = sum(self.cache_read_input_tokens, other.cache_read_input_tokens);
+        if self.service_tier.is_none() {
+            self.service_tier = other.service_tier;
+        }
+        self.extra.clear();
+    }
+}
+
+impl std::iter::Sum for TokenUsage {
+    fn sum<I: Iterator<Item = TokenUsage>>(iter: I) -> Self {
+        iter.fold(TokenUsage::default(), |total, usage| total + usage)
+    }
+}
+
+impl<'a> std::iter::Sum<&'a TokenUsage> for TokenUsage {
+    fn sum<I: Iterator<Item = &'a TokenUsage>>(iter: I) -> Self {
+        iter.cloned().sum()
+    }
 }
 
 impl ClaudeResponse {
@@ -55,6 +240,7 @@ impl ClaudeResponse {
             content,
             raw_json: None,
             metadata: None,
+            attempts: 1,
         }
     }
     
@@ -65,29 +251,55 @@ impl ClaudeResponse {
             content,
             raw_json: Some(raw_json),
             metadata,
+            attempts: 1,
+        }
+    }
+    
+    /// The raw JSON as typed CLI messages
+    /// 
+    /// Stream-json responses yield every message, JSON responses their single
+    /// result message and text responses nothing.
+    pub fn messages(&self) -> Vec<CliMessage> {
+        match &self.raw_json {
+            Some(serde_json::Value::Array(messages)) => messages
+                .iter()
+                .filter_map(|message| CliMessage::deserialize(message).ok())
+                .collect(),
+            Some(value) => CliMessage::deserialize(value).ok().into_iter().collect(),
+            None => Vec::new(),
         }
     }
     
     /// Extract metadata from raw JSON response
+    /// 
+    /// For stream-json output (an array of messages) the final `result`
+    /// message is used, falling back to the last message carrying a session id.
     fn extract_metadata(json: &serde_json::Value) -> Option<ResponseMetadata> {
+        if let Some(messages) = json.as_array() {
+            let result = messages.iter()
+                .rev()
+                .find(|m| m.get("type").and_then(|v| v.as_str()) == Some("result"))
+                .or_else(|| messages.iter().rev().find(|m| m.get("session_id").is_some()))?;
+            return Self::extract_metadata(result);
+        }
+        
         let session_id = json.get("session_id")?.as_str()?.to_string();
+        let is_result = json.get("type").and_then(|v| v.as_str()) == Some("result");
+        let (schema, cost_usd) = ResultSchema::read_cost(|field| json.get(field).and_then(|v| v.as_f64()));
         
         Some(ResponseMetadata {
             session_id,
-            cost_usd: json.get("cost_usd").and_then(|v| v.as_f64()),
+            cost_usd,
             duration_ms: json.get("duration_ms").and_then(|v| v.as_u64()),
-            tokens_used: json.get("message")
-                .and_then(|m| m.get("usage"))
-                .map(|usage| TokenUsage {
-                    input_tokens: usage.get("input_tokens").and_then(|v| v.as_u64()),
-                    output_tokens: usage.get("output_tokens").and_then(|v| v.as_u64()),
-                    cache_creation_input_tokens: usage.get("cache_creation_input_tokens").and_then(|v| v.as_u64()),
-                    cache_read_input_tokens: usage.get("cache_read_input_tokens").and_then(|v| v.as_u64()),
-                }),
+            tokens_used: json.get("usage")
+                .or_else(|| json.get("message").and_then(|m| m.get("usage")))
+                .and_then(|usage| TokenUsage::deserialize(usage).ok()),
             model: json.get("message")
                 .and_then(|m| m.get("model"))
                 .and_then(|v| v.as_str())
                 .map(String::from),
+            attempts: 1,
+            schema: is_result.then_some(schema),
         })
     }
 }
@@ -153,4 +365,45 @@ impl Cost {
             usd: self.usd + other.usd,
         }
     }
-}
\ No newline at end of file
+}
+
+/// A Claude Code CLI version as reported by `claude --version`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct CliVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl CliVersion {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self { major, minor, patch }
+    }
+    
+    /// Parse the output of `claude --version`, e.g. `1.0.17 (Claude Code)`
+    pub fn parse(output: &str) -> Option<Self> {
+        let token = output.split_whitespace().find(|t| t.starts_with(|c: char| c.is_ascii_digit()))?;
+        // Ignore pre-release and build suffixes such as `-beta.1` or `+abc`
+        let core = token.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|p| p.parse::<u32>());
+        
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+        let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+        Some(Self { major, minor, patch })
+    }
+}
+
+impl std::fmt::Display for CliVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl std::str::FromStr for CliVersion {
+    type Err = crate::Error;
+    
+    fn from_str(s: &str) -> crate::Result<Self> {
+        Self::parse(s).ok_or_else(|| crate::Error::InvalidInput(format!("Invalid CLI version: {}", s)))
+    }
+}
@@ -0,0 +1,134 @@
+use clau_core::{CliVersion, Config, Error, Result, StreamFormat};
+use crate::command::ClaudeCommand;
+use std::collections::BTreeSet;
+use tokio::time::{timeout, Duration};
+use tracing::debug;
+
+/// A configuration setting and the CLI flag it is passed through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlagRequirement {
+    /// Name of the [`Config`] field
+    pub setting: &'static str,
+    pub flag: &'static str,
+}
+
+/// Flags needed to honor every setting in `config`
+pub fn required_flags(config: &Config) -> Vec<FlagRequirement> {
+    let mut required = Vec::new();
+    let mut require = |setting, flag| required.push(FlagRequirement { setting, flag });
+    
+    if config.stream_format != StreamFormat::Text {
+        require("stream_format", "--output-format");
+    }
+    if config.verbose || config.stream_format == StreamFormat::StreamJson {
+        require("verbose", "--verbose");
+    }
+    if config.system_prompt.is_some() {
+        require("system_prompt", "--system-prompt");
+    }
+    if config.model.is_some() {
+        require("model", "--model");
+    }
+    if config.mcp_config_path.is_some() {
+        require("mcp_config_path", "--mcp-config");
+    }
+    if config.allowed_tools.is_some() {
+        require("allowed_tools", "--allowedTools");
+    }
+    if config.max_tokens.is_some() {
+        require("max_tokens", "--max-tokens");
+    }
+    if !config.add_dirs.is_empty() {
+        require("add_dirs", "--add-dir");
+    }
+    if config.resume_session.is_some() {
+        require("resume_session", "--resume");
+    }
+    
+    required
+}
+
+/// What the installed CLI can do
+/// 
+/// Built from `claude --version` and the flags listed by `claude --help`.
+/// When the help output could not be read, nothing is known and every
+/// setting is allowed through.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    pub version: Option<CliVersion>,
+    flags: BTreeSet<String>,
+}
+
+impl Capabilities {
+    /// Capabilities of a CLI we know nothing about; permits everything
+    pub fn unknown() -> Self {
+        Self::default()
+    }
+    
+    /// Collect every `--flag` mentioned in `claude --help` output
+    pub fn from_help(version: Option<CliVersion>, help: &str) -> Self {
+        let flags = help
+            .split(|c: char| c.is_whitespace() || c == ',' || c == '=' || c == '[' || c == '(')
+            .filter(|token| token.starts_with("--") && token.len() > 2)
+            .map(|token| {
+                token
+                    .trim_end_matches(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+                    .to_string()
+            })
+            .collect();
+        
+        Self { version, flags }
+    }
+    
+    /// Whether the help output was understood
+    pub fn is_known(&self) -> bool {
+        !self.flags.is_empty()
+    }
+    
+    /// Whether `flag` is supported; always true when capabilities are unknown
+    pub fn supports(&self, flag: &str) -> bool {
+        !self.is_known() || self.flags.contains(flag)
+    }
+    
+    /// Fail with a [`Error::ConfigError`] naming the first setting the CLI cannot honor
+    pub fn check(&self, config: &Config) -> Result<()> {
+        self.check_flags(required_flags(config))
+    }
+    
+    pub(crate) fn check_flags(&self, required: impl IntoIterator<Item = FlagRequirement>) -> Result<()> {
+        for requirement in required {
+            if !self.supports(requirement.flag) {
+                let cli = match self.version {
+                    Some(version) => format!("Claude Code {}", version),
+                    None => "the installed Claude Code".to_string(),
+                };
+                return Err(Error::ConfigError(format!(
+                    "`{}` needs the {} flag, which {} does not support",
+                    requirement.setting, requirement.flag, cli,
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Probe the CLI with `--help`
+/// 
+/// `version` is what `claude --version` reported, if it is known; it is not
+/// probed again here.
+pub async fn detect_capabilities(config: &Config, version: Option<CliVersion>) -> Result<Capabilities> {
+    let mut cmd = ClaudeCommand::base(config)?.with_args(["--help"]).to_command();
+    cmd.kill_on_drop(true);
+    
+    let timeout_secs = config.timeout_secs.unwrap_or(30);
+    let output = match timeout(Duration::from_secs(timeout_secs), cmd.output()).await {
+        Ok(Ok(output)) if output.status.success() => output,
+        _ => {
+            debug!("Could not read claude --help; skipping capability checks");
+            return Ok(Capabilities { version, ..Capabilities::unknown() });
+        }
+    };
+    
+    let help = String::from_utf8_lossy(&output.stdout);
+    Ok(Capabilities::from_help(version, &help))
+}
@@ -0,0 +1,249 @@
+use clau_core::{CliVersion, Config, Error, Result};
+use crate::capabilities::Capabilities;
+use crate::process::{build_args, process_failure, redacted_command_line, run_claude};
+use crate::transport::{SubprocessTransport, Transport};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// One recorded Claude invocation
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Interaction {
+    /// CLI arguments, excluding the binary and the prompt
+    pub args: Vec<String>,
+    pub query: String,
+    /// Stdout, split into lines
+    pub stdout: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+    /// Process exit code; `None` if it was killed by a signal
+    pub exit_code: Option<i32>,
+}
+
+impl Interaction {
+    fn matches(&self, args: &[String], query: &str) -> bool {
+        self.args == args && self.query == query
+    }
+    
+    fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+    
+    fn failure(&self) -> Error {
+        let command = redacted_command_line("claude", &self.args, true);
+        process_failure(&command, self.exit_code, None, &self.stdout.join("\n"), &self.stderr)
+    }
+}
+
+/// A file of recorded interactions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Load a cassette from a JSON file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+    
+    /// Write the cassette to a JSON file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Run the real CLI and append every invocation to the cassette
+    Record,
+    /// Serve invocations from the cassette without spawning `claude`
+    Replay,
+}
+
+/// A transport that records CLI invocations to disk and replays them later
+/// 
+/// Interactions are matched on the resolved CLI arguments and the prompt.
+/// Identical requests are served in recorded order; once they are used up the
+/// last match is repeated. In record mode each query runs to completion
+/// before its lines are streamed back, and the cassette is saved after every
+/// interaction; version and capability checks then go to the real CLI.
+/// 
+/// # Examples
+/// 
+/// ```rust,no_run
+/// # use clau_core::*;
+/// # use clau_runtime::{Client, cassette::CassetteTransport};
+/// # #[tokio::main]
+/// # async fn main() -> clau_core::Result<()> {
+/// // Once, with a logged-in CLI:
+/// let client = Client::builder()
+///     .transport(CassetteTransport::record("tests/cassettes/hello.json"))
+///     .build();
+/// client.send("Hello").await?;
+/// 
+/// // In CI, offline:
+/// let client = Client::builder()
+///     .transport(CassetteTransport::replay("tests/cassettes/hello.json")?)
+///     .build();
+/// client.send("Hello").await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct CassetteTransport {
+    mode: CassetteMode,
+    path: PathBuf,
+    state: Arc<Mutex<ReplayState>>,
+}
+
+#[derive(Default)]
+struct ReplayState {
+    cassette: Cassette,
+    used: Vec<bool>,
+}
+
+impl CassetteTransport {
+    /// Record to `path`, replacing any existing cassette there
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self {
+            mode: CassetteMode::Record,
+            path: path.into(),
+            state: Arc::new(Mutex::new(ReplayState::default())),
+        }
+    }
+    
+    /// Replay the cassette stored at `path`
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let cassette = Cassette::load(&path)?;
+        let used = vec![false; cassette.interactions.len()];
+        Ok(Self {
+            mode: CassetteMode::Replay,
+            path,
+            state: Arc::new(Mutex::new(ReplayState { cassette, used })),
+        })
+    }
+    
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+    
+    /// Interactions recorded or loaded so far
+    pub fn cassette(&self) -> Cassette {
+        self.state.lock().unwrap().cassette.clone()
+    }
+    
+    async fn interaction(&self, config: &Config, query: &str, cancel: CancellationToken) -> Result<Interaction> {
+        let args = build_args(config);
+        
+        match self.mode {
+            CassetteMode::Replay => self.find(&args, query),
+            CassetteMode::Record => {
+                let output = run_claude(config, query, cancel).await?;
+                let interaction = Interaction {
+                    args,
+                    query: query.to_string(),
+                    stdout: output.stdout.lines().map(String::from).collect(),
+                    stderr: output.stderr,
+                    exit_code: output.status.code(),
+                };
+                
+                let cassette = {
+                    let mut state = self.state.lock().unwrap();
+                    state.cassette.interactions.push(interaction.clone());
+                    state.used.push(true);
+                    state.cassette.clone()
+                };
+                cassette.save(&self.path)?;
+                debug!("Recorded interaction to {}", self.path.display());
+                
+                Ok(interaction)
+            }
+        }
+    }
+    
+    fn find(&self, args: &[String], query: &str) -> Result<Interaction> {
+        let mut state = self.state.lock().unwrap();
+        let ReplayState { cassette, used } = &mut *state;
+        
+        let matching: Vec<usize> = cassette.interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| i.matches(args, query))
+            .map(|(index, _)| index)
+            .collect();
+        
+        let index = matching.iter()
+            .copied()
+            .find(|&index| !used[index])
+            .or_else(|| matching.last().copied())
+            .ok_or_else(|| Error::InvalidInput(format!(
+                "No recorded interaction in {} for args {:?} and query {:?}",
+                self.path.display(),
+                args,
+                query,
+            )))?;
+        
+        used[index] = true;
+        Ok(cassette.interactions[index].clone())
+    }
+}
+
+#[async_trait]
+impl Transport for CassetteTransport {
+    async fn execute(&self, config: &Config, query: &str, cancel: CancellationToken) -> Result<String> {
+        let interaction = self.interaction(config, query, cancel).await?;
+        if !interaction.succeeded() {
+            return Err(interaction.failure());
+        }
+        
+        let mut stdout = interaction.stdout.join("\n");
+        stdout.push('\n');
+        Ok(stdout)
+    }
+    
+    async fn stream(
+        &self,
+        config: &Config,
+        query: &str,
+        cancel: CancellationToken,
+    ) -> Result<mpsc::Receiver<Result<String>>> {
+        let mut config = config.clone();
+        config.stream_format = clau_core::StreamFormat::StreamJson;
+        
+        let interaction = self.interaction(&config, query, cancel).await?;
+        
+        let (tx, rx) = mpsc::channel(interaction.stdout.len() + 1);
+        for line in interaction.stdout.iter().filter(|line| !line.trim().is_empty()) {
+            let _ = tx.try_send(Ok(line.clone()));
+        }
+        if !interaction.succeeded() {
+            let _ = tx.try_send(Err(interaction.failure()));
+        }
+        Ok(rx)
+    }
+    
+    async fn cli_version(&self, config: &Config) -> Result<CliVersion> {
+        match self.mode {
+            CassetteMode::Record => SubprocessTransport.cli_version(config).await,
+            CassetteMode::Replay => Err(Error::ConfigError(
+                "A replayed cassette does not run the claude CLI".to_string(),
+            )),
+        }
+    }
+    
+    async fn capabilities(&self, config: &Config, version: Option<CliVersion>) -> Result<Capabilities> {
+        match self.mode {
+            CassetteMode::Record => SubprocessTransport.capabilities(config, version).await,
+            CassetteMode::Replay => Ok(Capabilities::unknown()),
+        }
+    }
+}
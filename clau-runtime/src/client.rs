@@ -1,7 +1,12 @@
-use clau_core::{Config, Message, Result, SessionId, StreamFormat, ClaudeCliResponse, ClaudeResponse};
-use crate::{MessageStream, process::execute_claude};
+use clau_core::{CliMessage, CliVersion, Config, ConfigOverrides, Error, Message, OutputKind, OutputLimits, OverflowPolicy, Result, RetryPolicy, SessionId, StreamFormat, ClaudeCliResponse, ClaudeResponse, ResultSchema, VersionPolicy};
+use crate::{ClaudeCommand, InteractiveSession, MessageStream, capabilities::{Capabilities, FlagRequirement}, concurrency::{ConcurrencyLimiter, ConcurrencyPermit, QueueMetrics, DEFAULT_CALLER}, config_home::ensure_config_home, stream::MessageParser, transport::{SubprocessTransport, Transport}};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, OnceCell, RwLock};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
 
 /// High-level client for interacting with Claude Code CLI
 /// 
@@ -41,13 +46,63 @@ use tokio::sync::mpsc;
 #[derive(Clone)]
 pub struct Client {
     config: Arc<Config>,
+    transport: Arc<dyn Transport>,
+    /// Latest CLI session id for each session handle passed to [`QueryBuilder::session`]
+    sessions: Arc<RwLock<HashMap<SessionId, SessionId>>>,
+    /// CLI version, detected on first use
+    cli_version: Arc<OnceCell<CliVersion>>,
+    /// CLI flags, detected on first use
+    capabilities: Arc<OnceCell<Capabilities>>,
+    /// Set once `Config::config_home` exists
+    config_home_ready: Arc<OnceCell<()>>,
+    /// Shared by every clone; `None` means unlimited
+    limiter: Option<Arc<ConcurrencyLimiter>>,
+    /// Whose queue this client's queries wait in when fair queuing is on
+    caller: Arc<str>,
 }
 
 impl Client {
     /// Create a new client with the given configuration
     pub fn new(config: Config) -> Self {
+        Self::with_transport(config, SubprocessTransport)
+    }
+    
+    /// Create a new client that runs queries through a custom transport
+    pub fn with_transport(config: Config, transport: impl Transport + 'static) -> Self {
         Self {
             config: Arc::new(config),
+            transport: Arc::new(transport),
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            cli_version: Arc::new(OnceCell::new()),
+            capabilities: Arc::new(OnceCell::new()),
+            config_home_ready: Arc::new(OnceCell::new()),
+            limiter: None,
+            caller: Arc::from(DEFAULT_CALLER),
+        }
+    }
+    
+    /// A clone of this client whose queries queue as `caller`
+    /// 
+    /// With [`ClientBuilder::fair_queuing`] each caller waits in its own line
+    /// and free slots rotate between the lines. All clones still share one
+    /// concurrency limit, sessions and CLI probes.
+    pub fn for_caller(&self, caller: impl Into<String>) -> Self {
+        Self {
+            caller: Arc::from(caller.into()),
+            ..self.clone()
+        }
+    }
+    
+    /// Current queue depth and slot usage, if a concurrency limit is set
+    pub fn queue_metrics(&self) -> Option<QueueMetrics> {
+        self.limiter.as_ref().map(|limiter| limiter.metrics())
+    }
+    
+    /// Wait for a process slot; always succeeds at once without a limit
+    async fn acquire_slot(&self, cancel: &CancellationToken) -> Result<Option<ConcurrencyPermit>> {
+        match &self.limiter {
+            Some(limiter) => limiter.acquire(&self.caller, cancel).await.map(Some),
+            None => Ok(None),
         }
     }
     
@@ -106,6 +161,10 @@ impl Client {
     /// including metadata like costs, session IDs, and the raw JSON for 
     /// advanced parsing or storage.
     /// 
+    /// A run the CLI reports as failed is an error, never a response, even
+    /// when its result carries text. [`Error::run_failure`] gives the run's
+    /// turn count, cost and session.
+    /// 
     /// # Examples
     /// 
     /// ```rust,no_run
@@ -134,10 +193,49 @@ impl Client {
     /// # }
     /// ```
     pub async fn send_full(&self, query: &str) -> Result<ClaudeResponse> {
-        let output = execute_claude(&self.config, query).await?;
+        self.execute(&self.config, query, CancellationToken::new()).await
+    }
+    
+    /// Run a query with an explicit configuration, retrying per its retry policy
+    async fn execute(&self, config: &Config, query: &str, cancel: CancellationToken) -> Result<ClaudeResponse> {
+        self.prepare(config, &[]).await?;
+        
+        let policy = &config.retry_policy;
+        let mut attempt = 1;
+        loop {
+            let error = match self.execute_once(config, query, cancel.clone()).await {
+                Ok(mut response) => {
+                    response.attempts = attempt;
+                    if let Some(metadata) = response.metadata.as_mut() {
+                        metadata.attempts = attempt;
+                    }
+                    return Ok(response);
+                }
+                Err(e) if policy.should_retry(attempt, &e) => e,
+                Err(e) => return Err(e),
+            };
+            
+            let delay = policy.delay(attempt, &error);
+            warn!(
+                "Claude query attempt {}/{} failed: {}; retrying in {:?}",
+                attempt, policy.max_attempts, error, delay,
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = cancel.cancelled() => return Err(Error::Cancelled),
+            }
+            attempt += 1;
+        }
+    }
+    
+    /// Run a query once and parse the output
+    async fn execute_once(&self, config: &Config, query: &str, cancel: CancellationToken) -> Result<ClaudeResponse> {
+        let permit = self.acquire_slot(&cancel).await?;
+        let output = self.transport.execute(config, query, cancel).await?;
+        drop(permit);
         
         // Parse response based on format
-        match self.config.stream_format {
+        match config.stream_format {
             StreamFormat::Text => {
                 Ok(ClaudeResponse::text(output.trim().to_string()))
             }
@@ -145,12 +243,19 @@ impl Client {
                 // Parse the JSON response from claude CLI
                 let json_value: serde_json::Value = serde_json::from_str(&output)?;
                 let claude_response: ClaudeCliResponse = serde_json::from_value(json_value.clone())?;
+                if claude_response.schema() == ResultSchema::Unknown {
+                    warn!("Claude CLI result JSON has no known cost field; the CLI's output format may have changed");
+                }
+                if let Some(error) = claude_response.error() {
+                    return Err(error);
+                }
                 Ok(ClaudeResponse::with_json(claude_response.result, json_value))
             }
             StreamFormat::StreamJson => {
                 // For stream-json, we need to parse multiple JSON lines
                 let mut result = String::new();
-                let mut all_json = Vec::new();
+                let mut all_json = VecDeque::new();
+                let limits = config.output_limits;
                 
                 for line in output.lines() {
                     if line.trim().is_empty() {
@@ -158,42 +263,174 @@ impl Client {
                     }
                     // Try to parse as a message
                     if let Ok(msg) = serde_json::from_str::<serde_json::Value>(line) {
-                        all_json.push(msg.clone());
-                        
-                        // Check if it's an assistant message
-                        if msg.get("type").and_then(|v| v.as_str()) == Some("assistant") {
-                            // Extract text from the message content
-                            if let Some(message) = msg.get("message") {
-                                if let Some(content_array) = message.get("content").and_then(|v| v.as_array()) {
-                                    for content_item in content_array {
-                                        if content_item.get("type").and_then(|v| v.as_str()) == Some("text") {
-                                            if let Some(text) = content_item.get("text").and_then(|v| v.as_str()) {
-                                                result.push_str(text);
-                                            }
-                                        }
-                                    }
+                        match CliMessage::deserialize(&msg) {
+                            Ok(CliMessage::Assistant(assistant)) => result.push_str(&assistant.message.text()),
+                            Ok(CliMessage::Result(message)) => {
+                                if let Some(failure) = message.failure() {
+                                    return Err(Error::run_failed(failure));
                                 }
                             }
+                            _ => {}
+                        }
+                        
+                        all_json.push_back(msg);
+                        if let Some(max) = limits.max_raw_messages.filter(|&max| all_json.len() > max) {
+                            if limits.on_overflow != OverflowPolicy::Truncate {
+                                return Err(Error::OutputLimitExceeded { kind: OutputKind::RawMessages, limit: max });
+                            }
+                            all_json.pop_front();
                         }
                     }
                 }
                 
                 // Return the response with all JSON messages as an array
-                let raw_json = serde_json::Value::Array(all_json);
+                let raw_json = serde_json::Value::Array(all_json.into());
                 Ok(ClaudeResponse::with_json(result, raw_json))
             }
         }
     }
+    
+    /// Start a persistent interactive session using this client's configuration
+    /// 
+    /// Interactive sessions always spawn the `claude` CLI, whatever transport
+    /// the client uses. See [`InteractiveSession`] for details.
+    pub async fn interactive(&self) -> Result<InteractiveSession> {
+        let input_format = FlagRequirement { setting: "interactive session", flag: "--input-format" };
+        self.prepare(&self.config, &[input_format]).await?;
+        let permit = self.acquire_slot(&CancellationToken::new()).await?;
+        let mut session = InteractiveSession::start(&self.config).await?;
+        session.permit = permit;
+        Ok(session)
+    }
+    
+    /// Version of the Claude CLI this client runs
+    /// 
+    /// Parsed from `claude --version` on first call and cached afterwards.
+    /// 
+    /// # Examples
+    /// 
+    /// ```rust,no_run
+    /// # use clau_core::*;
+    /// # use clau_runtime::Client;
+    /// # #[tokio::main]
+    /// # async fn main() -> clau_core::Result<()> {
+    /// let client = Client::new(Config::default());
+    /// let version = client.cli_version().await?;
+    /// if version < CliVersion::new(1, 0, 0) {
+    ///     eprintln!("Please upgrade Claude Code (found {})", version);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn cli_version(&self) -> Result<CliVersion> {
+        self.ensure_config_home().await?;
+        self.cli_version
+            .get_or_try_init(|| self.transport.cli_version(&self.config))
+            .await
+            .copied()
+    }
+    
+    /// Flags supported by the Claude CLI this client runs
+    /// 
+    /// Probed with `claude --help` on first call and cached afterwards.
+    pub async fn capabilities(&self) -> Result<Capabilities> {
+        self.ensure_config_home().await?;
+        self.capabilities
+            .get_or_try_init(|| async {
+                let version = self.cli_version().await.ok();
+                self.transport.capabilities(&self.config, version).await
+            })
+            .await
+            .cloned()
+    }
+    
+    /// Everything that has to hold before a query spawns the CLI
+    async fn prepare(&self, config: &Config, extra: &[FlagRequirement]) -> Result<()> {
+        self.ensure_config_home().await?;
+        self.check_cli_version().await?;
+        self.check_capabilities(config, extra).await
+    }
+    
+    /// Create the client's config home on first use
+    async fn ensure_config_home(&self) -> Result<()> {
+        self.config_home_ready
+            .get_or_try_init(|| async {
+                // Copying the template is blocking file IO
+                let config = self.config.clone();
+                tokio::task::spawn_blocking(move || ensure_config_home(&config))
+                    .await
+                    .map_err(|e| Error::Io(std::io::Error::other(e)))?
+            })
+            .await
+            .map(|_| ())
+    }
+    
+    /// Reject settings the CLI cannot honor before spawning it
+    async fn check_capabilities(&self, config: &Config, extra: &[FlagRequirement]) -> Result<()> {
+        if !config.check_capabilities {
+            return Ok(());
+        }
+        let capabilities = self.capabilities().await?;
+        capabilities.check(config)?;
+        capabilities.check_flags(extra.iter().copied())
+    }
+    
+    /// Compare the CLI version against `Config::min_cli_version`
+    async fn check_cli_version(&self) -> Result<()> {
+        let Some(required) = self.config.min_cli_version else {
+            return Ok(());
+        };
+        
+        let found = self.cli_version().await?;
+        if found >= required {
+            return Ok(());
+        }
+        
+        match self.config.version_policy {
+            VersionPolicy::Require => Err(Error::UnsupportedCliVersion { found, required }),
+            VersionPolicy::Warn => {
+                warn!("Claude Code {} is older than the required {}", found, required);
+                Ok(())
+            }
+        }
+    }
+    
+    /// Resolve the CLI session id to resume for a session handle
+    async fn resolve_session(&self, handle: &SessionId) -> SessionId {
+        let sessions = self.sessions.read().await;
+        sessions.get(handle).cloned().unwrap_or_else(|| handle.clone())
+    }
+    
+    /// Remember the session id the CLI reported so the next turn resumes it
+    async fn record_session(&self, handle: &SessionId, session_id: &str) {
+        if session_id.is_empty() {
+            return;
+        }
+        let mut sessions = self.sessions.write().await;
+        sessions.insert(handle.clone(), SessionId::new(session_id));
+    }
 }
 
 pub struct ClientBuilder {
     config: Config,
+    transport: Option<Arc<dyn Transport>>,
+    max_concurrency: Option<usize>,
+    fair_queuing: bool,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ClientBuilder {
     pub fn new() -> Self {
         Self {
             config: Config::default(),
+            transport: None,
+            max_concurrency: None,
+            fair_queuing: false,
         }
     }
     
@@ -233,8 +470,92 @@ impl ClientBuilder {
         self
     }
     
+    /// Let queries run as long as they take; pair with an idle timeout
+    pub fn no_timeout(mut self) -> Self {
+        self.config.timeout_secs = None;
+        self
+    }
+    
+    /// Kill queries that produce no output line for `idle_secs`
+    /// 
+    /// Only stream-json queries and streams are watched; text and JSON output
+    /// arrive all at once at the end.
+    pub fn idle_timeout_secs(mut self, idle_secs: u64) -> Self {
+        self.config.idle_timeout_secs = Some(idle_secs);
+        self
+    }
+    
+    /// Use a specific `claude` executable instead of looking it up in `PATH`
+    pub fn claude_binary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.claude_binary = Some(path.into());
+        self
+    }
+    
+    /// Retry transient failures of `send`/`send_full` queries
+    /// 
+    /// Streams are never retried, since messages may already have been consumed.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.config.retry_policy = policy;
+        self
+    }
+    
+    /// Cap how much CLI output a query may hold in memory
+    pub fn output_limits(mut self, limits: OutputLimits) -> Self {
+        self.config.output_limits = limits;
+        self
+    }
+    
+    /// Give this client its own CLI config directory
+    /// 
+    /// Credentials, settings and session history live there instead of in
+    /// `~/.claude`, so clients for different tenants stay apart. The directory
+    /// is created before the first query if it does not exist.
+    pub fn config_home(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.config_home = Some(dir.into());
+        self
+    }
+    
+    /// Like [`config_home`](Self::config_home), copying `template` into place
+    /// when the directory does not exist yet
+    pub fn config_home_from_template(mut self, dir: impl Into<PathBuf>, template: impl Into<PathBuf>) -> Self {
+        self.config.config_home = Some(dir.into());
+        self.config.config_home_template = Some(template.into());
+        self
+    }
+    
+    /// Run at most `n` queries at once across this client and all its clones
+    /// 
+    /// Further queries wait for a slot; see [`Client::queue_metrics`].
+    /// Streams and interactive sessions hold their slot until they finish.
+    pub fn max_concurrency(mut self, n: usize) -> Self {
+        self.max_concurrency = Some(n);
+        self
+    }
+    
+    /// Give each caller its own queue and serve them in turn
+    /// 
+    /// Callers are set with [`Client::for_caller`]. Only has an effect
+    /// together with [`max_concurrency`](Self::max_concurrency).
+    pub fn fair_queuing(mut self, fair: bool) -> Self {
+        self.fair_queuing = fair;
+        self
+    }
+    
+    /// Run queries through a custom transport instead of the `claude` CLI
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+    
     pub fn build(self) -> Client {
-        Client::new(self.config)
+        let mut client = Client::new(self.config);
+        if let Some(transport) = self.transport {
+            client.transport = transport;
+        }
+        if let Some(n) = self.max_concurrency {
+            client.limiter = Some(Arc::new(ConcurrencyLimiter::new(n, self.fair_queuing)));
+        }
+        client
     }
 }
 
@@ -242,7 +563,8 @@ pub struct QueryBuilder {
     client: Client,
     query: String,
     session_id: Option<SessionId>,
-    format: Option<StreamFormat>,
+    overrides: ConfigOverrides,
+    cancel: Option<CancellationToken>,
 }
 
 impl QueryBuilder {
@@ -251,62 +573,217 @@ impl QueryBuilder {
             client,
             query,
             session_id: None,
-            format: None,
+            overrides: ConfigOverrides::default(),
+            cancel: None,
         }
     }
     
+    /// Continue an existing CLI session
+    /// 
+    /// The query runs with `--resume <id>`. The CLI may hand back a new session
+    /// id for the continued conversation; the client remembers it, so passing
+    /// the same handle on the next turn resumes the latest state. Text output
+    /// carries no session id, so use `Json` or `StreamJson` for multi-turn use.
     pub fn session(mut self, session_id: SessionId) -> Self {
         self.session_id = Some(session_id);
         self
     }
     
+    /// Output format for this query only
     pub fn format(mut self, format: StreamFormat) -> Self {
-        self.format = Some(format);
+        self.overrides.stream_format = Some(format);
+        self
+    }
+    
+    /// Model for this query only
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.overrides.model = Some(model.into());
+        self
+    }
+    
+    /// System prompt for this query only
+    pub fn system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.overrides.system_prompt = Some(prompt.into());
+        self
+    }
+    
+    /// Allowed tools for this query only, replacing the client's list
+    pub fn allowed_tools(mut self, tools: Vec<String>) -> Self {
+        self.overrides.allowed_tools = Some(tools);
+        self
+    }
+    
+    /// Token limit for this query only
+    pub fn max_tokens(mut self, max_tokens: usize) -> Self {
+        self.overrides.max_tokens = Some(max_tokens);
+        self
+    }
+    
+    /// Timeout for this query only
+    pub fn timeout_secs(mut self, timeout_secs: u64) -> Self {
+        self.overrides.timeout_secs = Some(timeout_secs);
+        self
+    }
+    
+    /// Idle timeout for this query only
+    pub fn idle_timeout_secs(mut self, idle_secs: u64) -> Self {
+        self.overrides.idle_timeout_secs = Some(idle_secs);
+        self
+    }
+    
+    /// Working directory for this query only
+    pub fn working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.overrides.working_dir = Some(dir.into());
+        self
+    }
+    
+    /// Let Claude access another directory for this query (`--add-dir`)
+    pub fn add_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.overrides.add_dirs.push(dir.into());
+        self
+    }
+    
+    /// Set an environment variable for this query's Claude process
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.overrides.env.insert(key.into(), value.into());
+        self
+    }
+    
+    /// Remove an inherited environment variable for this query
+    pub fn env_remove(mut self, key: impl Into<String>) -> Self {
+        self.overrides.env_remove.push(key.into());
+        self
+    }
+    
+    /// Run this query's Claude process with only the variables set via [`env`](Self::env)
+    pub fn env_clear(mut self) -> Self {
+        self.overrides.env_clear = Some(true);
+        self
+    }
+    
+    /// Cancel this query through `token`
+    /// 
+    /// Cancelling the token kills the Claude process and every subprocess it
+    /// started; `send`/`send_full` then return [`Error::Cancelled`](clau_core::Error::Cancelled).
+    /// One token can be shared by several queries to cancel them together.
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+    
+    /// Replace all per-query overrides at once
+    pub fn overrides(mut self, overrides: ConfigOverrides) -> Self {
+        self.overrides = overrides;
         self
     }
     
     /// Send the query and return just the text content
     pub async fn send(self) -> Result<String> {
-        self.client.send(&self.query).await
+        let response = self.send_full().await?;
+        Ok(response.content)
     }
     
     /// Send the query and return the full response with metadata and raw JSON
     pub async fn send_full(self) -> Result<ClaudeResponse> {
-        self.client.send_full(&self.query).await
+        let config = self.resolve_config().await;
+        let cancel = self.cancel_for_query();
+        let result = self.client.execute(&config, &self.query, cancel).await;
+        
+        // A failed run still started a session, which the next query resumes
+        let session_id = match &result {
+            Ok(response) => response.metadata.as_ref().map(|metadata| metadata.session_id.as_str()),
+            Err(e) => e.run_failure().map(|failure| failure.session_id.as_str()),
+        };
+        if let (Some(handle), Some(session_id)) = (&self.session_id, session_id) {
+            self.client.record_session(handle, session_id).await;
+        }
+        
+        result
+    }
+    
+    /// The command [`send`](Self::send) would run, without running it
+    /// 
+    /// Nothing is spawned and no config home is created. The command is what
+    /// the subprocess transport runs; a custom transport may do something else.
+    pub async fn dry_run(self) -> Result<ClaudeCommand> {
+        let config = self.resolve_config().await;
+        ClaudeCommand::new(&config, &self.query)
+    }
+    
+    /// Token for this query alone, cancelled along with any caller token
+    fn cancel_for_query(&self) -> CancellationToken {
+        match &self.cancel {
+            Some(token) => token.child_token(),
+            None => CancellationToken::new(),
+        }
     }
     
+    /// Client configuration with this query's overrides and session applied
+    async fn resolve_config(&self) -> Config {
+        let mut config = self.client.config.with_overrides(&self.overrides);
+        if let Some(handle) = &self.session_id {
+            config.resume_session = Some(self.client.resolve_session(handle).await);
+        }
+        config
+    }
+    
+    /// Stream messages as the CLI produces them
+    /// 
+    /// Always runs Claude with `--output-format stream-json --verbose` and yields
+    /// each line as soon as it is written, so progress is visible during long
+    /// agent runs. Dropping the stream or calling [`MessageStream::cancel`]
+    /// kills the underlying process tree.
     pub async fn stream(self) -> Result<MessageStream> {
-        // For now, streaming is simulated by getting the full response
-        // and sending it as a single message
-        let (tx, rx) = mpsc::channel(100);
+        if let Some(format) = self.overrides.stream_format.filter(|f| *f != StreamFormat::StreamJson) {
+            debug!("Streaming always uses stream-json; ignoring requested format {:?}", format);
+        }
         
-        let format = self.format.unwrap_or(self.client.config.stream_format);
+        // Capabilities are checked for the format that actually runs
+        let mut config = self.resolve_config().await;
+        config.stream_format = StreamFormat::StreamJson;
+        let cancel = self.cancel_for_query();
+        self.client.prepare(&config, &[]).await?;
+        let permit = self.client.acquire_slot(&cancel).await?;
+        let mut lines = self.client.transport.stream(&config, &self.query, cancel.clone()).await?;
+        let (tx, rx) = mpsc::channel(100);
         let client = self.client;
-        let query = self.query;
+        let handle = self.session_id;
         
         tokio::spawn(async move {
-            match client.send(&query).await {
-                Ok(response) => {
-                    // Send the response as a single assistant message
-                    let msg = Message::Assistant {
-                        content: response,
-                        meta: clau_core::MessageMeta {
-                            session_id: "stream-session".to_string(),
-                            timestamp: Some(std::time::SystemTime::now()),
-                            cost_usd: None,
-                            duration_ms: None,
-                            tokens_used: None,
-                        },
-                    };
-                    let _ = tx.send(Ok(msg)).await;
+            // The slot stays taken until the process output is exhausted
+            let _permit = permit;
+            let parser = MessageParser::new(StreamFormat::StreamJson);
+            while let Some(line) = lines.recv().await {
+                let mut failure = None;
+                let item = match line {
+                    Ok(line) => match parser.parse_line(&line) {
+                        Ok(Some(message)) => {
+                            if let (Some(handle), Message::Init { meta } | Message::Result { meta, .. }) = (&handle, &message) {
+                                client.record_session(handle, &meta.session_id).await;
+                            }
+                            if let Message::Result { failure: Some(run), .. } = &message {
+                                failure = Some(Error::run_failed(run.clone()));
+                            }
+                            Ok(message)
+                        }
+                        Ok(None) => continue,
+                        Err(e) => Err(e),
+                    },
+                    Err(e) => Err(e),
+                };
+                if tx.send(item).await.is_err() {
+                    break;
                 }
-                Err(e) => {
-                    let _ = tx.send(Err(e)).await;
+                // An error result ends the stream with its typed error, ahead
+                // of the generic failure the non-zero exit would produce
+                if let Some(error) = failure {
+                    let _ = tx.send(Err(error)).await;
+                    break;
                 }
             }
         });
         
-        Ok(MessageStream::new(rx, format))
+        Ok(MessageStream::new(rx, StreamFormat::StreamJson).with_cancel(cancel))
     }
     
     pub async fn parse_output<T: serde::de::DeserializeOwned>(self) -> Result<T> {
@@ -0,0 +1,185 @@
+use clau_core::{Config, Result};
+use crate::config_home::CLAUDE_CONFIG_DIR_ENV;
+use crate::discovery::find_claude_binary;
+use crate::process::{build_args, redacted_command_line};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// Everything needed to run the `claude` CLI for a query
+///
+/// This is exactly what the SDK spawns: the resolved executable, the full
+/// argument list, the prompt if it goes over stdin, the working directory and
+/// the environment changes. Use [`QueryBuilder::dry_run`](crate::QueryBuilder::dry_run)
+/// to get one for a query without running it, and [`ClaudeCommand::to_shell`]
+/// to reproduce it from a terminal.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use clau_core::*;
+/// # use clau_runtime::ClaudeCommand;
+/// # fn main() -> clau_core::Result<()> {
+/// let config = Config::builder().model("claude-sonnet-4-20250514").build();
+/// let command = ClaudeCommand::new(&config, "Hello")?;
+/// assert!(command.args.contains(&"--model".to_string()));
+/// println!("{}", command.to_shell());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaudeCommand {
+    /// The `claude` executable
+    pub program: PathBuf,
+    /// Arguments after the program, including the prompt unless it goes over stdin
+    pub args: Vec<String>,
+    /// Prompt written to stdin, when it is not passed as an argument
+    pub stdin: Option<String>,
+    pub working_dir: Option<PathBuf>,
+    /// Variables set on top of the environment
+    pub env: BTreeMap<String, String>,
+    /// Inherited variables that are removed
+    pub env_remove: Vec<String>,
+    /// Whether the process starts from an empty environment
+    pub env_clear: bool,
+    /// Whether the last argument is the prompt
+    prompt_in_args: bool,
+}
+
+impl ClaudeCommand {
+    /// The command a one-shot query runs with
+    ///
+    /// Fails if the `claude` executable cannot be found.
+    pub fn new(config: &Config, query: &str) -> Result<Self> {
+        let mut command = Self::base(config)?;
+        
+        if config.prompt_delivery.uses_stdin(query) {
+            // `claude -p` reads the prompt from stdin when none is given as an argument
+            command.stdin = Some(query.to_string());
+        } else {
+            command.args.push(query.to_string());
+            command.prompt_in_args = true;
+        }
+        
+        Ok(command)
+    }
+    
+    /// The command for `config` without a prompt
+    pub(crate) fn base(config: &Config) -> Result<Self> {
+        let mut env = config.env.clone();
+        if let Some(home) = &config.config_home {
+            env.insert(CLAUDE_CONFIG_DIR_ENV.to_string(), home.to_string_lossy().into_owned());
+        }
+        
+        Ok(Self {
+            program: find_claude_binary(config)?,
+            args: build_args(config),
+            stdin: None,
+            working_dir: config.working_dir.clone(),
+            env,
+            env_remove: config.env_remove.clone(),
+            env_clear: config.env_clear,
+            prompt_in_args: false,
+        })
+    }
+    
+    /// The same program and environment with different arguments
+    pub(crate) fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self.prompt_in_args = false;
+        self
+    }
+    
+    /// The program followed by its arguments
+    pub fn argv(&self) -> Vec<String> {
+        std::iter::once(self.program.to_string_lossy().into_owned())
+            .chain(self.args.iter().cloned())
+            .collect()
+    }
+    
+    /// A `tokio` command ready to spawn
+    ///
+    /// Stdin is piped when the prompt goes over stdin and null otherwise;
+    /// writing the prompt is up to the caller.
+    pub fn to_command(&self) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args);
+        
+        if let Some(dir) = &self.working_dir {
+            cmd.current_dir(dir);
+        }
+        if self.env_clear {
+            cmd.env_clear();
+        }
+        for key in &self.env_remove {
+            cmd.env_remove(key);
+        }
+        cmd.envs(&self.env);
+        
+        cmd.stdin(if self.stdin.is_some() { Stdio::piped() } else { Stdio::null() });
+        cmd
+    }
+    
+    /// A POSIX shell command line that runs exactly this command
+    ///
+    /// Includes the prompt and every environment value, so treat the result
+    /// as sensitive. Use [`redacted`](Self::redacted) for logs.
+    pub fn to_shell(&self) -> String {
+        let mut parts = Vec::new();
+        
+        if let Some(prompt) = &self.stdin {
+            parts.push(format!("printf '%s' {} |", shell_escape(prompt)));
+        }
+        if self.env_clear || !self.env_remove.is_empty() || !self.env.is_empty() {
+            parts.push("env".to_string());
+            if self.env_clear {
+                parts.push("-i".to_string());
+            }
+            for key in &self.env_remove {
+                parts.push(format!("-u {}", shell_escape(key)));
+            }
+            for (key, value) in &self.env {
+                parts.push(shell_escape(&format!("{}={}", key, value)));
+            }
+        }
+        parts.extend(self.argv().iter().map(|arg| shell_escape(arg)));
+        
+        let line = parts.join(" ");
+        match &self.working_dir {
+            Some(dir) => format!("cd {} && {}", shell_escape(&dir.to_string_lossy()), line),
+            None => line,
+        }
+    }
+    
+    /// The command line with the prompt and system prompt left out, safe to log
+    pub fn redacted(&self) -> String {
+        let args = match self.prompt_in_args {
+            true => &self.args[..self.args.len() - 1],
+            false => &self.args[..],
+        };
+        redacted_command_line(&self.program.to_string_lossy(), args, self.prompt_in_args)
+    }
+}
+
+impl fmt::Display for ClaudeCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_shell())
+    }
+}
+
+/// Quote `value` for a POSIX shell, leaving plainly safe words alone
+pub fn shell_escape(value: &str) -> String {
+    let safe = !value.is_empty()
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || "_-+=/.,:@%".contains(c));
+    if safe {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
@@ -0,0 +1,191 @@
+use clau_core::{Error, Result};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+/// Caller name used when none is given
+pub const DEFAULT_CALLER: &str = "default";
+
+/// Caps how many `claude` processes a client and all its clones run at once
+///
+/// Queries beyond the limit wait in line. Without fair queuing the line is
+/// first come, first served. With fair queuing every caller (see
+/// [`Client::for_caller`](crate::Client::for_caller)) gets its own line and
+/// free slots go to the lines in turn, so a caller that queues hundreds of
+/// batch queries only delays another caller's query by one slot.
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    max_concurrency: usize,
+    fair: bool,
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    running: usize,
+    /// Waiters per caller, oldest first
+    queues: HashMap<String, VecDeque<oneshot::Sender<ConcurrencyPermit>>>,
+    /// Callers with waiters, in the order they are served
+    turns: VecDeque<String>,
+    queued: usize,
+    peak_queued: usize,
+    total_started: u64,
+    total_wait: Duration,
+}
+
+/// Snapshot of a [`ConcurrencyLimiter`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueMetrics {
+    pub max_concurrency: usize,
+    /// Queries holding a slot right now
+    pub running: usize,
+    /// Queries waiting for a slot right now
+    pub queued: usize,
+    /// Waiting queries per caller
+    pub queued_by_caller: HashMap<String, usize>,
+    /// Largest queue depth seen so far
+    pub peak_queued: usize,
+    /// Queries that have been given a slot so far
+    pub total_started: u64,
+    /// Time those queries spent waiting, summed
+    pub total_wait: Duration,
+}
+
+impl ConcurrencyLimiter {
+    /// Allow at most `max_concurrency` queries at once (at least 1)
+    pub fn new(max_concurrency: usize, fair: bool) -> Self {
+        Self {
+            max_concurrency: max_concurrency.max(1),
+            fair,
+            state: Mutex::new(LimiterState::default()),
+        }
+    }
+    
+    /// Wait for a free slot on behalf of `caller`
+    ///
+    /// The slot is held until the returned permit is dropped. Fails with
+    /// [`Error::Cancelled`] if `cancel` fires first.
+    pub async fn acquire(self: &Arc<Self>, caller: &str, cancel: &CancellationToken) -> Result<ConcurrencyPermit> {
+        let queued_at = Instant::now();
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            if state.running < self.max_concurrency && state.queued == 0 {
+                state.running += 1;
+                state.total_started += 1;
+                return Ok(ConcurrencyPermit::new(self));
+            }
+            
+            let key = if self.fair { caller } else { DEFAULT_CALLER };
+            let (tx, rx) = oneshot::channel();
+            let queue = state.queues.entry(key.to_string()).or_default();
+            if queue.is_empty() {
+                state.turns.push_back(key.to_string());
+            }
+            state.queues.get_mut(key).unwrap().push_back(tx);
+            state.queued += 1;
+            state.peak_queued = state.peak_queued.max(state.queued);
+            rx
+        };
+        
+        // A permit sent to a waiter that has given up is dropped with the
+        // channel, which hands the slot on to the next waiter
+        let permit = tokio::select! {
+            permit = receiver => Some(permit.map_err(|_| Error::StreamClosed)?),
+            _ = cancel.cancelled() => None,
+        };
+        let Some(permit) = permit else {
+            self.state.lock().unwrap().prune_abandoned();
+            return Err(Error::Cancelled);
+        };
+        
+        self.state.lock().unwrap().total_wait += queued_at.elapsed();
+        Ok(permit)
+    }
+    
+    pub fn metrics(&self) -> QueueMetrics {
+        let mut state = self.state.lock().unwrap();
+        state.prune_abandoned();
+        QueueMetrics {
+            max_concurrency: self.max_concurrency,
+            running: state.running,
+            queued: state.queued,
+            queued_by_caller: state.queues
+                .iter()
+                .filter(|(_, queue)| !queue.is_empty())
+                .map(|(caller, queue)| (caller.clone(), queue.len()))
+                .collect(),
+            peak_queued: state.peak_queued,
+            total_started: state.total_started,
+            total_wait: state.total_wait,
+        }
+    }
+    
+    /// Hand a freed slot to the next waiter, or give it back
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(waiter) = state.next_waiter() {
+            match waiter.send(ConcurrencyPermit::new(self)) {
+                Ok(()) => {
+                    state.total_started += 1;
+                    return;
+                }
+                // The waiter gave up; its permit must not release the slot again
+                Err(permit) => permit.disarm(),
+            }
+        }
+        state.running -= 1;
+    }
+}
+
+impl LimiterState {
+    /// Pop the next waiter, taking callers in turn
+    fn next_waiter(&mut self) -> Option<oneshot::Sender<ConcurrencyPermit>> {
+        let caller = self.turns.pop_front()?;
+        let queue = self.queues.get_mut(&caller)?;
+        let waiter = queue.pop_front()?;
+        if !queue.is_empty() {
+            self.turns.push_back(caller);
+        }
+        self.queued -= 1;
+        Some(waiter)
+    }
+    
+    /// Forget waiters that stopped waiting, so the metrics stay accurate
+    fn prune_abandoned(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.retain(|waiter| !waiter.is_closed());
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+        let queues = &self.queues;
+        self.turns.retain(|caller| queues.contains_key(caller));
+        self.queued = self.queues.values().map(VecDeque::len).sum();
+    }
+}
+
+/// A slot in a [`ConcurrencyLimiter`], released on drop
+#[derive(Debug)]
+pub struct ConcurrencyPermit {
+    limiter: Option<Arc<ConcurrencyLimiter>>,
+}
+
+impl ConcurrencyPermit {
+    fn new(limiter: &Arc<ConcurrencyLimiter>) -> Self {
+        Self {
+            limiter: Some(limiter.clone()),
+        }
+    }
+    
+    fn disarm(mut self) {
+        self.limiter = None;
+    }
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.limiter.take() {
+            limiter.release();
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use clau_core::{Config, Result};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::debug;
+
+/// Environment variable the CLI reads its config directory from
+pub const CLAUDE_CONFIG_DIR_ENV: &str = "CLAUDE_CONFIG_DIR";
+
+/// Create [`Config::config_home`] if it does not exist yet
+///
+/// The directory is filled from [`Config::config_home_template`] when one is
+/// set. It is assembled under a temporary name and renamed into place, so
+/// clients racing to create the same home never see a half-copied template.
+/// On Unix the directory is readable by its owner only, as it ends up holding
+/// credentials.
+pub fn ensure_config_home(config: &Config) -> Result<()> {
+    let Some(home) = &config.config_home else {
+        return Ok(());
+    };
+    if home.is_dir() {
+        return Ok(());
+    }
+    
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let name = home.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let staging = home.with_file_name(format!(
+        ".{}.tmp-{}-{}",
+        name,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst),
+    ));
+    if let Some(parent) = home.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    
+    let built = match &config.config_home_template {
+        Some(template) => copy_dir(template, &staging),
+        None => std::fs::create_dir(&staging),
+    };
+    if let Err(e) = built.and_then(|_| restrict_permissions(&staging)) {
+        let _ = std::fs::remove_dir_all(&staging);
+        return Err(e.into());
+    }
+    
+    match std::fs::rename(&staging, home) {
+        Ok(()) => {
+            debug!("Created Claude config home {}", home.display());
+            Ok(())
+        }
+        // Another client created it first
+        Err(_) if home.is_dir() => {
+            let _ = std::fs::remove_dir_all(&staging);
+            Ok(())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&staging);
+            Err(e.into())
+        }
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    std::fs::create_dir(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn restrict_permissions(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use clau_core::{CliVersion, Config, Error, Result};
+use crate::command::ClaudeCommand;
+use std::path::{Path, PathBuf};
+use tokio::time::{timeout, Duration};
+use tracing::debug;
+
+/// Environment variable that points at the `claude` executable
+pub const CLAUDE_BIN_ENV: &str = "CLAUDE_BIN";
+
+/// Locate the `claude` executable
+/// 
+/// Checked in order: [`Config::claude_binary`], the `CLAUDE_BIN` environment
+/// variable, `PATH`, and finally the usual install locations (the CLI's own
+/// local install, npm/yarn global prefixes, Homebrew). An explicitly
+/// configured path or `CLAUDE_BIN` must exist; neither is ever silently
+/// replaced by a fallback.
+pub fn find_claude_binary(config: &Config) -> Result<PathBuf> {
+    if let Some(path) = &config.claude_binary {
+        return existing(path).ok_or_else(|| Error::BinaryNotFound {
+            searched: vec![path.clone()],
+        });
+    }
+    
+    if let Some(path) = std::env::var_os(CLAUDE_BIN_ENV).map(PathBuf::from) {
+        return existing(&path).ok_or(Error::BinaryNotFound { searched: vec![path] });
+    }
+    
+    let mut searched = Vec::new();
+    
+    if let Ok(path) = which::which("claude") {
+        return Ok(path);
+    }
+    searched.push(PathBuf::from("$PATH/claude"));
+    
+    for path in common_locations() {
+        if let Some(found) = existing(&path) {
+            debug!("Found claude at fallback location {}", found.display());
+            return Ok(found);
+        }
+        searched.push(path);
+    }
+    
+    Err(Error::BinaryNotFound { searched })
+}
+
+/// Run `claude --version` and parse the result
+pub async fn detect_cli_version(config: &Config) -> Result<CliVersion> {
+    let mut cmd = ClaudeCommand::base(config)?.with_args(["--version"]).to_command();
+    cmd.kill_on_drop(true);
+    
+    let timeout_secs = config.timeout_secs.unwrap_or(30);
+    let output = timeout(Duration::from_secs(timeout_secs), cmd.output())
+        .await
+        .map_err(|_| Error::Timeout(timeout_secs))??;
+    
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::ProcessError(format!("claude --version failed: {}", stderr)));
+    }
+    
+    CliVersion::parse(&stdout).ok_or_else(|| {
+        Error::ProcessError(format!("Unrecognized claude --version output: {}", stdout.trim()))
+    })
+}
+
+fn existing(path: &Path) -> Option<PathBuf> {
+    path.is_file().then(|| path.to_path_buf())
+}
+
+fn common_locations() -> Vec<PathBuf> {
+    let mut locations = Vec::new();
+    
+    if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")).map(PathBuf::from) {
+        locations.push(home.join(".claude/local/claude"));
+        locations.push(home.join(".npm-global/bin/claude"));
+        locations.push(home.join(".local/bin/claude"));
+        locations.push(home.join(".yarn/bin/claude"));
+        locations.push(home.join("node_modules/.bin/claude"));
+    }
+    locations.push(PathBuf::from("/usr/local/bin/claude"));
+    locations.push(PathBuf::from("/opt/homebrew/bin/claude"));
+    
+    locations
+}
@@ -0,0 +1,150 @@
+use clau_core::{Config, Error, Message, Result, StreamFormat};
+use crate::{ClaudeCommand, MessageStream, concurrency::ConcurrencyPermit, process::{forward_lines, spawn_piped}};
+use futures::StreamExt;
+use serde_json::json;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::ChildStdin;
+use tokio::time::{timeout, Duration};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// A long-lived Claude process driven over stream-json stdin/stdout
+/// 
+/// Unlike [`QueryBuilder`](crate::QueryBuilder), which starts a fresh `claude -p`
+/// for every call, an interactive session keeps one process running and feeds
+/// it user turns with `--input-format stream-json`. Context stays warm between
+/// turns and there is no per-turn startup cost.
+/// 
+/// # Examples
+/// 
+/// ```rust,no_run
+/// # use clau_core::*;
+/// # use clau_runtime::Client;
+/// # #[tokio::main]
+/// # async fn main() -> clau_core::Result<()> {
+/// let client = Client::new(Config::default());
+/// let mut session = client.interactive().await?;
+/// 
+/// let answer = session.ask("Pick a number between 1 and 10").await?;
+/// println!("{}", answer);
+/// let answer = session.ask("Now double it").await?;
+/// println!("{}", answer);
+/// 
+/// session.close().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct InteractiveSession {
+    stdin: Option<ChildStdin>,
+    messages: MessageStream,
+    cancel: CancellationToken,
+    close_timeout_secs: u64,
+    /// Concurrency slot held for the life of the process
+    pub(crate) permit: Option<ConcurrencyPermit>,
+}
+
+impl InteractiveSession {
+    /// Start a persistent Claude process for the given configuration
+    pub async fn start(config: &Config) -> Result<Self> {
+        let mut config = config.clone();
+        config.stream_format = StreamFormat::StreamJson;
+        
+        let mut claude = ClaudeCommand::base(&config)?;
+        claude.args.extend(["--input-format".to_string(), "stream-json".to_string()]);
+        let command = claude.redacted();
+        
+        debug!("Starting interactive Claude session: {}", command);
+        
+        let mut cmd = claude.to_command();
+        cmd.stdin(Stdio::piped());
+        let mut process = spawn_piped(cmd)?;
+        let stdin = process.take_stdin().ok_or(Error::StreamClosed)?;
+        
+        let cancel = CancellationToken::new();
+        let lines = forward_lines(process, command, None, None, config.output_limits, cancel.clone())?;
+        
+        Ok(Self {
+            stdin: Some(stdin),
+            messages: MessageStream::from_lines(lines).with_cancel(cancel.clone()),
+            cancel,
+            close_timeout_secs: config.timeout_secs.unwrap_or(30),
+            permit: None,
+        })
+    }
+    
+    /// Send a user turn to the running process
+    pub async fn send(&mut self, content: impl Into<String>) -> Result<()> {
+        let stdin = self.stdin.as_mut().ok_or(Error::StreamClosed)?;
+        
+        let message = json!({
+            "type": "user",
+            "message": {
+                "role": "user",
+                "content": content.into(),
+            },
+        });
+        let mut line = serde_json::to_string(&message)?;
+        line.push('\n');
+        
+        stdin.write_all(line.as_bytes()).await?;
+        stdin.flush().await?;
+        Ok(())
+    }
+    
+    /// Messages emitted by the process, across all turns
+    pub fn messages(&mut self) -> &mut MessageStream {
+        &mut self.messages
+    }
+    
+    /// Send a user turn and collect the assistant text up to its result
+    /// 
+    /// A turn whose result reports a failure returns that error instead of
+    /// the text; the session itself stays usable.
+    pub async fn ask(&mut self, content: impl Into<String>) -> Result<String> {
+        self.send(content).await?;
+        
+        let mut response = String::new();
+        while let Some(message) = self.messages.next().await {
+            match message? {
+                Message::Assistant { content, .. } => response.push_str(&content),
+                Message::Result { failure: Some(failure), .. } => return Err(Error::run_failed(failure)),
+                Message::Result { .. } => return Ok(response),
+                _ => {}
+            }
+        }
+        
+        Err(Error::StreamClosed)
+    }
+    
+    /// Close stdin and wait for the process to exit
+    /// 
+    /// Remaining messages are drained and discarded. If the process does not
+    /// exit within the configured timeout it is killed.
+    pub async fn close(mut self) -> Result<()> {
+        self.stdin.take();
+        
+        let messages = &mut self.messages;
+        let drain = async {
+            while let Some(message) = messages.next().await {
+                message?;
+            }
+            Ok(())
+        };
+        
+        let result = timeout(Duration::from_secs(self.close_timeout_secs), drain)
+            .await
+            .map_err(|_| Error::Timeout(self.close_timeout_secs))
+            .and_then(|r| r);
+        
+        self.cancel.cancel();
+        result
+    }
+    
+    /// Kill the process immediately
+    /// 
+    /// Dropping the session has the same effect.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
@@ -1,6 +1,21 @@
+pub mod capabilities;
+pub mod cassette;
+pub mod command;
+pub mod concurrency;
+pub mod config_home;
 pub mod process;
 pub mod stream;
 pub mod client;
+pub mod discovery;
+pub mod interactive;
+pub mod transport;
 
+pub use capabilities::Capabilities;
+pub use cassette::{CassetteMode, CassetteTransport};
 pub use client::{Client, QueryBuilder};
-pub use stream::MessageStream;
\ No newline at end of file
+pub use command::ClaudeCommand;
+pub use concurrency::QueueMetrics;
+pub use interactive::InteractiveSession;
+pub use stream::MessageStream;
+pub use transport::{ScriptedCall, ScriptedTransport, SubprocessTransport, Transport};
+pub use tokio_util::sync::CancellationToken;
\ No newline at end of file
@@ -1,27 +1,679 @@
-use clau_core::{Error, Result, Config, StreamFormat};
-use tokio::process::Command;
-use tokio::time::{timeout, Duration};
-use tracing::debug;
+use clau_core::{CliMessage, Error, OutputKind, OutputLimits, OverflowPolicy, ProcessFailure, QueryTimeout, Result, Config, StreamFormat, TimeoutKind};
+use crate::command::ClaudeCommand;
+use crate::stream::MessageParser;
+use std::process::{ExitStatus, Stdio};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, Command};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, warn};
 
 /// Execute a one-shot Claude command with timeout
 pub async fn execute_claude(config: &Config, query: &str) -> Result<String> {
+    execute_claude_with_cancel(config, query, CancellationToken::new()).await
+}
+
+/// Execute a one-shot Claude command that can be cancelled
+/// 
+/// Cancelling the token, hitting the timeout or dropping the returned future
+/// kills the Claude process together with any subprocesses it started.
+pub async fn execute_claude_with_cancel(
+    config: &Config,
+    query: &str,
+    cancel: CancellationToken,
+) -> Result<String> {
+    let output = run_claude(config, query, cancel).await?;
+    
+    if !output.status.success() {
+        return Err(output.failure());
+    }
+    
+    Ok(output.stdout)
+}
+
+/// Error for an unsuccessful run
+/// 
+/// An error `result` message on stdout describes the failure best; otherwise
+/// stderr is classified by [`Error::process_failed`].
+pub(crate) fn process_failure(
+    command: &str,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    stdout: &str,
+    stderr: &str,
+) -> Error {
+    stdout.lines()
+        .rev()
+        .find_map(result_error)
+        .unwrap_or_else(|| {
+            Error::process_failed(ProcessFailure::new(command, exit_code, signal, stderr, stdout))
+        })
+}
+
+/// Error reported by a stdout line, if it is an error `result` message
+pub(crate) fn result_error(line: &str) -> Option<Error> {
+    match serde_json::from_str(line).ok()? {
+        CliMessage::Result(result) => result.failure().map(Error::run_failed),
+        _ => None,
+    }
+}
+
+/// Everything a finished Claude process produced
+#[derive(Debug, Clone)]
+pub struct ProcessOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+    /// The command line that was run, with prompt contents redacted
+    pub command: String,
+}
+
+impl ProcessOutput {
+    /// The error describing this run, assuming it failed
+    pub fn failure(&self) -> Error {
+        let (exit_code, signal) = exit_parts(&self.status);
+        process_failure(&self.command, exit_code, signal, &self.stdout, &self.stderr)
+    }
+}
+
+/// Exit code and terminating signal of a finished process
+pub(crate) fn exit_parts(status: &ExitStatus) -> (Option<i32>, Option<i32>) {
+    #[cfg(unix)]
+    let signal = std::os::unix::process::ExitStatusExt::signal(status);
+    #[cfg(not(unix))]
+    let signal = None;
+    
+    (status.code(), signal)
+}
+
+/// Command line for error reports
+/// 
+/// System prompt values are replaced and the prompt itself only appears as a
+/// placeholder, so the result is safe to log.
+pub(crate) fn redacted_command_line<'a>(
+    program: &str,
+    args: impl IntoIterator<Item = &'a String>,
+    prompt_as_arg: bool,
+) -> String {
+    let mut parts = vec![program.to_string()];
+    let mut redact_next = false;
+    for arg in args {
+        if redact_next {
+            parts.push("<redacted>".to_string());
+            redact_next = false;
+            continue;
+        }
+        redact_next = matches!(arg.as_str(), "--system-prompt" | "--append-system-prompt");
+        parts.push(arg.clone());
+    }
+    if prompt_as_arg {
+        parts.push("<prompt>".to_string());
+    }
+    parts.join(" ")
+}
+
+/// Run Claude to completion and return its output whatever the exit status
+/// 
+/// Timeouts and cancellation still fail with [`Error::QueryTimedOut`] and
+/// [`Error::Cancelled`] after killing the process tree.
+pub async fn run_claude(
+    config: &Config,
+    query: &str,
+    cancel: CancellationToken,
+) -> Result<ProcessOutput> {
+    let claude = ClaudeCommand::new(config, query)?;
+    let command = claude.redacted();
+    
+    debug!("Executing Claude command: {}", command);
+    
+    let mut process = spawn_query(&claude)?;
+    let stdout = process.child.stdout.take().ok_or(Error::StreamClosed)?;
+    let stderr = process.child.stderr.take().ok_or(Error::StreamClosed)?;
+    
+    let limits = config.output_limits;
+    let stderr_overflow = CancellationToken::new();
+    let stderr_task = drain_stderr(stderr, limits, stderr_overflow.clone());
+    
+    let mut reader = BufReader::new(stdout);
+    let mut out = StdoutBuffer::new(limits, config.stream_format);
+    let mut line = Vec::new();
+    // Text and JSON output only arrive once the run is over, so silence says
+    // nothing about whether the process hangs
+    let idle_secs = config.idle_timeout_secs.filter(|_| config.stream_format == StreamFormat::StreamJson);
+    
+    // Resolves to `None` if the idle timeout expired
+    let run = async {
+        loop {
+            line.clear();
+            tokio::select! {
+                read = read_line_capped(&mut reader, &mut line, limits.max_stdout_bytes) => {
+                    let read = read?;
+                    if read == 0 {
+                        break;
+                    }
+                    if !out.push(&line, read) {
+                        return Err(Error::OutputLimitExceeded {
+                            kind: OutputKind::Stdout,
+                            limit: limits.max_stdout_bytes.unwrap_or_default(),
+                        });
+                    }
+                }
+                _ = expires_after(idle_secs) => return Ok(None),
+            }
+        }
+        let status = process.wait().await?;
+        Ok(Some(status))
+    };
     
-    let claude_binary = which::which("claude").map_err(|_| Error::BinaryNotFound)?;
+    // Execute the command with timeout
+    let outcome = tokio::select! {
+        status = run => match status {
+            Ok(status) => status.ok_or(TimeoutKind::Idle),
+            Err(e) => {
+                process.kill();
+                return Err(e);
+            }
+        },
+        _ = expires_after(config.timeout_secs) => Err(TimeoutKind::Total),
+        _ = stderr_overflow.cancelled() => {
+            process.kill();
+            return Err(Error::OutputLimitExceeded {
+                kind: OutputKind::Stderr,
+                limit: limits.max_stderr_bytes.unwrap_or_default(),
+            });
+        }
+        _ = cancel.cancelled() => {
+            process.kill();
+            return Err(Error::Cancelled);
+        }
+    };
+    
+    let status = match outcome {
+        Ok(status) => status,
+        Err(kind) => {
+            process.kill();
+            let secs = match kind {
+                TimeoutKind::Total => config.timeout_secs.unwrap_or_default(),
+                TimeoutKind::Idle => idle_secs.unwrap_or_default(),
+            };
+            let stdout = String::from_utf8_lossy(&out.kept);
+            return Err(timed_out(kind, secs, config.stream_format, &stdout));
+        }
+    };
+    let stderr = stderr_task.await.unwrap_or_default();
+    if stderr_overflow.is_cancelled() {
+        return Err(Error::OutputLimitExceeded {
+            kind: OutputKind::Stderr,
+            limit: limits.max_stderr_bytes.unwrap_or_default(),
+        });
+    }
     
-    let mut cmd = Command::new(claude_binary);
+    Ok(ProcessOutput {
+        status,
+        stdout: String::from_utf8_lossy(&out.into_bytes()).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        command,
+    })
+}
+
+/// Stdout of a one-shot query, held to [`OutputLimits::max_stdout_bytes`]
+struct StdoutBuffer {
+    kept: Vec<u8>,
+    /// Latest whole line that did not fit, if no line was kept after it
+    last_line: Vec<u8>,
+    limit: Option<usize>,
+    truncate: bool,
+    format: StreamFormat,
+    truncated: bool,
+}
+
+impl StdoutBuffer {
+    fn new(limits: OutputLimits, format: StreamFormat) -> Self {
+        Self {
+            kept: Vec::new(),
+            last_line: Vec::new(),
+            limit: limits.max_stdout_bytes,
+            // A JSON document cut short is no use to anyone
+            truncate: limits.on_overflow == OverflowPolicy::Truncate && format != StreamFormat::Json,
+            format,
+            truncated: false,
+        }
+    }
+    
+    /// Add a line of which `read` bytes were read and only `line` kept
+    /// 
+    /// Returns false if the line does not fit and truncation is off.
+    fn push(&mut self, line: &[u8], read: usize) -> bool {
+        let Some(limit) = self.limit else {
+            self.kept.extend_from_slice(line);
+            return true;
+        };
+        let whole = read == line.len();
+        let fits = whole && self.kept.len() + line.len() <= limit;
+        // Text has to stay contiguous, so nothing is added after a cut
+        if fits && !(self.truncated && self.format == StreamFormat::Text) {
+            self.kept.extend_from_slice(line);
+            self.last_line.clear();
+            return true;
+        }
+        if !self.truncate {
+            return false;
+        }
+        
+        let first_cut = !self.truncated;
+        if first_cut {
+            warn!("Claude stdout went over {} bytes; dropping what does not fit", limit);
+            self.truncated = true;
+        }
+        match self.format {
+            StreamFormat::Text if first_cut => {
+                let room = limit.saturating_sub(self.kept.len()).min(line.len());
+                self.kept.extend_from_slice(&line[..room]);
+            }
+            StreamFormat::Text => {}
+            StreamFormat::Json | StreamFormat::StreamJson => {
+                if whole {
+                    self.last_line = line.to_vec();
+                }
+            }
+        }
+        true
+    }
     
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.kept.append(&mut self.last_line);
+        self.kept
+    }
+}
+
+/// Read through the next newline into `buf`, keeping at most `limit` bytes
+/// 
+/// Returns how many bytes were read, 0 at EOF. Bytes past the limit are
+/// consumed and dropped, so an endless line never sits in memory.
+async fn read_line_capped<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    limit: Option<usize>,
+) -> std::io::Result<usize> {
+    let mut read = 0;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(read);
+        }
+        let newline = available.iter().position(|&b| b == b'\n');
+        let end = newline.map_or(available.len(), |pos| pos + 1);
+        let room = limit.map_or(end, |limit| limit.saturating_sub(buf.len()).min(end));
+        buf.extend_from_slice(&available[..room]);
+        reader.consume(end);
+        read += end;
+        if newline.is_some() {
+            return Ok(read);
+        }
+    }
+}
+
+/// Read stderr in the background, holding at most `max_stderr_bytes`
+/// 
+/// When truncating, the most recent bytes are kept. Otherwise `overflow` is
+/// cancelled as soon as the limit is passed and reading stops.
+fn drain_stderr(
+    mut stderr: ChildStderr,
+    limits: OutputLimits,
+    overflow: CancellationToken,
+) -> JoinHandle<Vec<u8>> {
+    tokio::spawn(async move {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 8192];
+        loop {
+            let read = match stderr.read(&mut chunk).await {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            buf.extend_from_slice(&chunk[..read]);
+            let Some(limit) = limits.max_stderr_bytes else {
+                continue;
+            };
+            if buf.len() > limit && limits.on_overflow != OverflowPolicy::Truncate {
+                overflow.cancel();
+                break;
+            }
+            // Trim in batches rather than shifting the buffer on every read
+            if buf.len() > 2 * limit.max(chunk.len()) {
+                buf.drain(..buf.len() - limit);
+            }
+        }
+        if let Some(limit) = limits.max_stderr_bytes.filter(|&limit| buf.len() > limit) {
+            buf.drain(..buf.len() - limit);
+        }
+        buf
+    })
+}
+
+/// Sleep for `secs`, or forever when there is no limit
+async fn expires_after(secs: Option<u64>) {
+    match secs {
+        Some(secs) => sleep(Duration::from_secs(secs)).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Timeout error carrying the messages parsed from the output received so far
+fn timed_out(kind: TimeoutKind, secs: u64, format: StreamFormat, stdout: &str) -> Error {
+    let parser = MessageParser::new(StreamFormat::StreamJson);
+    let partial = match format {
+        StreamFormat::Text if stdout.trim().is_empty() => Vec::new(),
+        StreamFormat::Text => vec![parser.parse_text_response(stdout.trim())],
+        StreamFormat::Json | StreamFormat::StreamJson => stdout
+            .lines()
+            .filter_map(|line| parser.parse_line(line).ok().flatten())
+            .collect(),
+    };
+    Error::QueryTimedOut(Box::new(QueryTimeout { kind, secs, partial }))
+}
+
+/// Spawn Claude with stream-json output and forward each stdout line as it arrives
+/// 
+/// The returned receiver yields one item per non-empty line. If the process
+/// exits unsuccessfully or the timeout elapses, a final `Err` is sent before
+/// the channel closes. Cancelling the token or dropping the receiver kills the
+/// process tree.
+pub async fn stream_claude(
+    config: &Config,
+    query: &str,
+    cancel: CancellationToken,
+) -> Result<mpsc::Receiver<Result<String>>> {
+    let mut config = config.clone();
+    config.stream_format = StreamFormat::StreamJson;
+    
+    let claude = ClaudeCommand::new(&config, query)?;
+    let command = claude.redacted();
+    
+    debug!("Spawning Claude command: {}", command);
+    
+    let process = spawn_query(&claude)?;
+    forward_lines(process, command, config.timeout_secs, config.idle_timeout_secs, config.output_limits, cancel)
+}
+
+/// A spawned Claude process and the process group it leads
+/// 
+/// Unless the process has been waited on successfully, dropping or killing it
+/// also kills every subprocess the CLI started (tool invocations, MCP servers).
+pub(crate) struct ProcessTree {
+    child: Child,
+    reaped: bool,
+}
+
+impl ProcessTree {
+    /// Take the piped stdin handle, if stdin was configured as piped
+    pub(crate) fn take_stdin(&mut self) -> Option<ChildStdin> {
+        self.child.stdin.take()
+    }
+    
+    /// Wait for the process to exit
+    pub(crate) async fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        let status = self.child.wait().await?;
+        self.reaped = true;
+        Ok(status)
+    }
+    
+    /// Kill the process and its whole process group
+    pub(crate) fn kill(&mut self) {
+        if self.reaped {
+            return;
+        }
+        
+        #[cfg(unix)]
+        if let Some(pid) = self.child.id() {
+            // The child was spawned as leader of its own group, so the negated
+            // pid addresses exactly the processes it started
+            unsafe {
+                libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+            }
+        }
+        
+        let _ = self.child.start_kill();
+    }
+}
+
+impl Drop for ProcessTree {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// Spawn a command with piped stdout/stderr in its own process group
+pub(crate) fn spawn_piped(mut cmd: Command) -> Result<ProcessTree> {
+    cmd.stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    
+    #[cfg(unix)]
+    cmd.process_group(0);
+    
+    Ok(ProcessTree {
+        child: cmd.spawn()?,
+        reaped: false,
+    })
+}
+
+/// Spawn a one-shot query, writing its stdin prompt if it has one
+/// 
+/// The prompt is written from a separate task while the caller reads stdout,
+/// so a prompt larger than the pipe buffer cannot deadlock against output
+/// the CLI is already producing. Closing stdin marks the end of the prompt.
+fn spawn_query(command: &ClaudeCommand) -> Result<ProcessTree> {
+    let mut process = spawn_piped(command.to_command())?;
+    
+    if let Some(prompt) = &command.stdin {
+        let mut stdin = process.take_stdin().ok_or(Error::StreamClosed)?;
+        let prompt = prompt.clone();
+        tokio::spawn(async move {
+            if let Err(e) = stdin.write_all(prompt.as_bytes()).await {
+                debug!("Failed to write prompt to Claude stdin: {}", e);
+            }
+        });
+    }
+    
+    Ok(process)
+}
+
+/// The most recent stdout lines, within the line and byte counts of [`OutputLimits`]
+struct RecentLines {
+    lines: std::collections::VecDeque<String>,
+    bytes: usize,
+    max_lines: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
+impl RecentLines {
+    fn new(limits: OutputLimits) -> Self {
+        Self {
+            lines: Default::default(),
+            bytes: 0,
+            max_lines: limits.max_raw_messages,
+            max_bytes: limits.max_stdout_bytes,
+        }
+    }
+    
+    fn push(&mut self, line: &str) {
+        self.lines.push_back(line.to_string());
+        self.bytes += line.len() + 1;
+        while self.max_lines.is_some_and(|max| self.lines.len() > max)
+            || self.max_bytes.is_some_and(|max| self.bytes > max)
+        {
+            let Some(dropped) = self.lines.pop_front() else { break };
+            self.bytes -= dropped.len() + 1;
+        }
+    }
+    
+    fn joined(&self) -> String {
+        self.lines.iter().flat_map(|line| [line.as_str(), "\n"]).collect()
+    }
+}
+
+/// How reading a process's stdout ended
+enum ReadEnd {
+    Eof,
+    /// The receiver was dropped; nobody is listening any more
+    Abandoned,
+    /// No line arrived within the idle timeout
+    Idle,
+    /// A line was longer than the stdout limit
+    Overflow,
+}
+
+/// Forward stdout lines of a spawned process over a channel
+/// 
+/// Reading stops at EOF, when `deadline_secs` elapses, when no line arrives
+/// for `idle_secs`, when `cancel` fires or when the receiver is dropped. A
+/// non-zero exit, read failure, timeout, cancellation or output over `limits`
+/// is reported as a final `Err` item.
+pub(crate) fn forward_lines(
+    mut process: ProcessTree,
+    command: String,
+    deadline_secs: Option<u64>,
+    idle_secs: Option<u64>,
+    limits: OutputLimits,
+    cancel: CancellationToken,
+) -> Result<mpsc::Receiver<Result<String>>> {
+    let stdout = process.child.stdout.take().ok_or(Error::StreamClosed)?;
+    let stderr = process.child.stderr.take().ok_or(Error::StreamClosed)?;
+    
+    // Drain stderr concurrently so a chatty process can't block on a full pipe
+    let stderr_overflow = CancellationToken::new();
+    let stderr_task = drain_stderr(stderr, limits, stderr_overflow.clone());
+    
+    let (tx, rx) = mpsc::channel(100);
+    
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(stdout);
+        let mut buf = Vec::new();
+        
+        // Recent output, kept for the error report if the process fails
+        let mut stdout_tail = Vec::new();
+        // Lines forwarded, for the partial messages of a timeout error; only
+        // kept when a timeout can actually fire, and only as many as `limits` allow
+        let mut received = RecentLines::new(limits);
+        let keep_received = deadline_secs.is_some() || idle_secs.is_some();
+        
+        let read_all = async {
+            loop {
+                buf.clear();
+                let read = tokio::select! {
+                    read = read_line_capped(&mut reader, &mut buf, limits.max_stdout_bytes) => read?,
+                    _ = expires_after(idle_secs) => return Ok(ReadEnd::Idle),
+                };
+                if read == 0 {
+                    return Ok::<_, std::io::Error>(ReadEnd::Eof);
+                }
+                if read > buf.len() {
+                    if limits.on_overflow != OverflowPolicy::Truncate {
+                        return Ok(ReadEnd::Overflow);
+                    }
+                    warn!("Dropping a {}-byte line of Claude output", read);
+                    continue;
+                }
+                let line = String::from_utf8_lossy(&buf);
+                let line = line.trim_end_matches(['\n', '\r']);
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if stdout_tail.len() > 2 * ProcessFailure::OUTPUT_LIMIT {
+                    stdout_tail.drain(..stdout_tail.len() - ProcessFailure::OUTPUT_LIMIT);
+                }
+                stdout_tail.extend_from_slice(line.as_bytes());
+                stdout_tail.push(b'\n');
+                if keep_received {
+                    received.push(line);
+                }
+                if tx.send(Ok(line.to_string())).await.is_err() {
+                    return Ok(ReadEnd::Abandoned);
+                }
+            }
+        };
+        
+        tokio::select! {
+            outcome = read_all => match outcome {
+                Ok(ReadEnd::Eof) => {
+                    let status = process.wait().await;
+                    let stderr = stderr_task.await.unwrap_or_default();
+                    let stderr = String::from_utf8_lossy(&stderr);
+                    match status {
+                        _ if stderr_overflow.is_cancelled() => {
+                            let _ = tx.send(Err(Error::OutputLimitExceeded {
+                                kind: OutputKind::Stderr,
+                                limit: limits.max_stderr_bytes.unwrap_or_default(),
+                            })).await;
+                        }
+                        Ok(status) if status.success() => {}
+                        Ok(status) => {
+                            let (exit_code, signal) = exit_parts(&status);
+                            let failure = ProcessFailure::new(command, exit_code, signal, &stderr, &String::from_utf8_lossy(&stdout_tail));
+                            let _ = tx.send(Err(Error::process_failed(failure))).await;
+                        }
+                        Err(e) => {
+                            let _ = tx.send(Err(Error::Io(e))).await;
+                        }
+                    }
+                }
+                Ok(ReadEnd::Abandoned) => process.kill(),
+                Ok(ReadEnd::Overflow) => {
+                    process.kill();
+                    let _ = tx.send(Err(Error::OutputLimitExceeded {
+                        kind: OutputKind::Stdout,
+                        limit: limits.max_stdout_bytes.unwrap_or_default(),
+                    })).await;
+                }
+                Ok(ReadEnd::Idle) => {
+                    process.kill();
+                    let error = timed_out(TimeoutKind::Idle, idle_secs.unwrap_or_default(), StreamFormat::StreamJson, &received.joined());
+                    let _ = tx.send(Err(error)).await;
+                }
+                Err(e) => {
+                    error!("Failed to read Claude output: {}", e);
+                    process.kill();
+                    let _ = tx.send(Err(Error::Io(e))).await;
+                }
+            },
+            _ = expires_after(deadline_secs) => {
+                process.kill();
+                let error = timed_out(TimeoutKind::Total, deadline_secs.unwrap_or_default(), StreamFormat::StreamJson, &received.joined());
+                let _ = tx.send(Err(error)).await;
+            }
+            _ = stderr_overflow.cancelled() => {
+                process.kill();
+                let _ = tx.send(Err(Error::OutputLimitExceeded {
+                    kind: OutputKind::Stderr,
+                    limit: limits.max_stderr_bytes.unwrap_or_default(),
+                })).await;
+            }
+            _ = cancel.cancelled() => {
+                process.kill();
+                let _ = tx.send(Err(Error::Cancelled)).await;
+            }
+        }
+    });
+    
+    Ok(rx)
+}
+
+/// CLI arguments for the given configuration, excluding the binary and the prompt
+pub fn build_args(config: &Config) -> Vec<String> {
     // Always use non-interactive mode for SDK
-    cmd.arg("-p");
+    let mut args = vec!["-p".to_string()];
     
     // Add format flag
     match config.stream_format {
         StreamFormat::Json => {
-            cmd.arg("--output-format").arg("json");
+            args.extend(["--output-format".to_string(), "json".to_string()]);
         }
         StreamFormat::StreamJson => {
-            cmd.arg("--output-format").arg("stream-json");
+            args.extend(["--output-format".to_string(), "stream-json".to_string()]);
             // stream-json requires verbose flag
-            cmd.arg("--verbose");
+            args.push("--verbose".to_string());
         }
         StreamFormat::Text => {
             // Text is default, no need to specify
@@ -30,48 +682,39 @@ pub async fn execute_claude(config: &Config, query: &str) -> Result<String> {
     
     // Add verbose flag if configured (and not already added for stream-json)
     if config.verbose && config.stream_format != StreamFormat::StreamJson {
-        cmd.arg("--verbose");
+        args.push("--verbose".to_string());
     }
     
     // Add optional flags
     if let Some(system_prompt) = &config.system_prompt {
-        cmd.arg("--system-prompt").arg(system_prompt);
+        args.extend(["--system-prompt".to_string(), system_prompt.clone()]);
     }
     
     if let Some(model) = &config.model {
-        cmd.arg("--model").arg(model);
+        args.extend(["--model".to_string(), model.clone()]);
     }
     
     if let Some(mcp_config_path) = &config.mcp_config_path {
-        cmd.arg("--mcp-config").arg(mcp_config_path);
+        args.extend(["--mcp-config".to_string(), mcp_config_path.to_string_lossy().into_owned()]);
     }
     
     if let Some(allowed_tools) = &config.allowed_tools {
         for tool in allowed_tools {
-            cmd.arg("--allowedTools").arg(tool);
+            args.extend(["--allowedTools".to_string(), tool.clone()]);
         }
     }
     
     if let Some(max_tokens) = &config.max_tokens {
-        cmd.arg("--max-tokens").arg(max_tokens.to_string());
+        args.extend(["--max-tokens".to_string(), max_tokens.to_string()]);
     }
     
-    // Add the query as the last argument
-    cmd.arg(query);
-    
-    debug!("Executing Claude command: {:?}", cmd);
-    
-    // Execute the command with timeout
-    let timeout_duration = Duration::from_secs(config.timeout_secs.unwrap_or(30));
-    let output = timeout(timeout_duration, cmd.output())
-        .await
-        .map_err(|_| Error::Timeout(config.timeout_secs.unwrap_or(30)))??;
+    for dir in &config.add_dirs {
+        args.extend(["--add-dir".to_string(), dir.to_string_lossy().into_owned()]);
+    }
     
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(Error::ProcessError(format!("Claude command failed: {}", stderr)));
+    if let Some(session_id) = &config.resume_session {
+        args.extend(["--resume".to_string(), session_id.to_string()]);
     }
     
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    Ok(stdout)
-}
\ No newline at end of file
+    args
+}
@@ -1,19 +1,76 @@
-use clau_core::{Error, Result, Message, StreamFormat};
+use clau_core::{CliMessage, Error, Result, Message, StreamFormat};
 use futures::{Stream, StreamExt};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use tokio::sync::mpsc;
-use tracing::error;
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::{debug, error};
 
 pub struct MessageStream {
     receiver: mpsc::Receiver<Result<Message>>,
+    cancel: Option<CancellationToken>,
+    /// Cancels the producing process when the stream is dropped
+    _drop_guard: Option<DropGuard>,
 }
 
 impl MessageStream {
     pub fn new(receiver: mpsc::Receiver<Result<Message>>, _format: StreamFormat) -> Self {
-        Self { receiver }
+        Self {
+            receiver,
+            cancel: None,
+            _drop_guard: None,
+        }
+    }
+    
+    /// Tie the producing process to this stream
+    /// 
+    /// [`cancel`](Self::cancel) and dropping the stream will both cancel `token`.
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self._drop_guard = Some(token.clone().drop_guard());
+        self.cancel = Some(token);
+        self
+    }
+    
+    /// Kill the process producing this stream
+    /// 
+    /// Messages already received remain readable; the stream then ends with
+    /// an [`Error::Cancelled`] item.
+    pub fn cancel(&self) {
+        if let Some(token) = &self.cancel {
+            token.cancel();
+        }
     }
     
+    /// A handle that can cancel this stream from another task
+    pub fn cancellation_token(&self) -> Option<CancellationToken> {
+        self.cancel.clone()
+    }
+    
+    /// Parse raw stream-json lines into messages as they arrive
+    pub fn from_lines(mut lines: mpsc::Receiver<Result<String>>) -> Self {
+        let (tx, rx) = mpsc::channel(100);
+        
+        tokio::spawn(async move {
+            let parser = MessageParser::new(StreamFormat::StreamJson);
+            while let Some(line) = lines.recv().await {
+                let item = match line.and_then(|line| parser.parse_line(&line)) {
+                    Ok(Some(message)) => Ok(message),
+                    Ok(None) => continue,
+                    Err(e) => Err(e),
+                };
+                if tx.send(item).await.is_err() {
+                    break;
+                }
+            }
+        });
+        
+        Self::new(rx, StreamFormat::StreamJson)
+    }
+    
+    /// Collect the assistant text up to the result
+    /// 
+    /// Fails with the run's error if the result reports a failure, even when
+    /// some text was already collected.
     pub async fn collect_full_response(mut self) -> Result<String> {
         let mut response = String::new();
         
@@ -22,6 +79,9 @@ impl MessageStream {
                 Message::Assistant { content, .. } => {
                     response.push_str(&content);
                 }
+                Message::Result { failure: Some(failure), .. } => {
+                    return Err(Error::run_failed(failure));
+                }
                 Message::Result { .. } => {
                     // End of conversation
                     break;
@@ -62,17 +122,33 @@ impl MessageParser {
                     return Ok(None);
                 }
                 
-                match serde_json::from_str::<Message>(line) {
-                    Ok(message) => Ok(Some(message)),
-                    Err(e) => {
-                        error!("Failed to parse message: {}, line: {}", e, line);
-                        Err(Error::SerializationError(e))
-                    }
+                if let Ok(message) = serde_json::from_str::<Message>(line) {
+                    return Ok(Some(message));
                 }
+                // Fall back to the shape the CLI actually emits
+                let Some(message) = self.parse_cli_line(line)? else {
+                    return Ok(None);
+                };
+                let simplified = message.to_message();
+                if simplified.is_none() {
+                    debug!("Skipping unknown message: {}", line);
+                }
+                Ok(simplified)
             }
         }
     }
     
+    /// Parse a stream-json line into the CLI's own message model
+    pub fn parse_cli_line(&self, line: &str) -> Result<Option<CliMessage>> {
+        if self.format == StreamFormat::Text || line.trim().is_empty() {
+            return Ok(None);
+        }
+        serde_json::from_str(line).map(Some).map_err(|e| {
+            error!("Failed to parse message: {}, line: {}", e, line);
+            Error::SerializationError(e)
+        })
+    }
+    
     pub fn parse_text_response(&self, text: &str) -> Message {
         // For text format, create a simple assistant message
         Message::Assistant {
@@ -83,6 +159,7 @@ impl MessageParser {
                 cost_usd: None,
                 duration_ms: None,
                 tokens_used: None,
+                extra: Default::default(),
             },
         }
     }
@@ -0,0 +1,176 @@
+use clau_core::{CliVersion, Config, Error, Result};
+use crate::capabilities::{detect_capabilities, Capabilities};
+use crate::discovery::detect_cli_version;
+use crate::process::{execute_claude_with_cancel, stream_claude};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// How a [`Client`](crate::Client) runs Claude
+/// 
+/// The default implementation, [`SubprocessTransport`], spawns the `claude`
+/// CLI. Alternative transports can serve canned output in tests, replay
+/// recordings, or forward queries to a remote executor.
+/// 
+/// Transports deal in raw CLI output; parsing into responses and messages
+/// stays in the client, so an alternative transport exercises the same
+/// parsing paths as the real CLI.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Run a query to completion and return everything written to stdout
+    async fn execute(&self, config: &Config, query: &str, cancel: CancellationToken) -> Result<String>;
+    
+    /// Run a query with stream-json output, yielding each stdout line as it arrives
+    /// 
+    /// The query must stop running once `cancel` fires.
+    async fn stream(
+        &self,
+        config: &Config,
+        query: &str,
+        cancel: CancellationToken,
+    ) -> Result<mpsc::Receiver<Result<String>>>;
+    
+    /// Version of the CLI behind this transport
+    /// 
+    /// Transports that do not run the CLI keep the default, which fails.
+    async fn cli_version(&self, _config: &Config) -> Result<CliVersion> {
+        Err(Error::ConfigError("This transport does not run the claude CLI".to_string()))
+    }
+    
+    /// Flags the CLI behind this transport accepts
+    /// 
+    /// `version` is the already known [`cli_version`](Self::cli_version), if
+    /// any. The default reports unknown capabilities, which permits every
+    /// setting.
+    async fn capabilities(&self, _config: &Config, _version: Option<CliVersion>) -> Result<Capabilities> {
+        Ok(Capabilities::unknown())
+    }
+}
+
+/// Runs queries by spawning the `claude` CLI
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SubprocessTransport;
+
+#[async_trait]
+impl Transport for SubprocessTransport {
+    async fn execute(&self, config: &Config, query: &str, cancel: CancellationToken) -> Result<String> {
+        execute_claude_with_cancel(config, query, cancel).await
+    }
+    
+    async fn stream(
+        &self,
+        config: &Config,
+        query: &str,
+        cancel: CancellationToken,
+    ) -> Result<mpsc::Receiver<Result<String>>> {
+        stream_claude(config, query, cancel).await
+    }
+    
+    async fn cli_version(&self, config: &Config) -> Result<CliVersion> {
+        detect_cli_version(config).await
+    }
+    
+    async fn capabilities(&self, config: &Config, version: Option<CliVersion>) -> Result<Capabilities> {
+        detect_capabilities(config, version).await
+    }
+}
+
+/// A query seen by a [`ScriptedTransport`]
+#[derive(Debug, Clone)]
+pub struct ScriptedCall {
+    pub config: Config,
+    pub query: String,
+}
+
+/// A transport that replies with scripted output instead of running Claude
+/// 
+/// Replies are served in the order they were pushed, one per query. Every
+/// query is recorded so tests can assert on the resolved configuration.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// # use clau_core::*;
+/// # use clau_runtime::{Client, transport::ScriptedTransport};
+/// # #[tokio::main]
+/// # async fn main() -> clau_core::Result<()> {
+/// let transport = ScriptedTransport::new().reply("4\n");
+/// let client = Client::builder().transport(transport.clone()).build();
+/// 
+/// assert_eq!(client.send("What is 2 + 2?").await?, "4");
+/// assert_eq!(transport.calls()[0].query, "What is 2 + 2?");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct ScriptedTransport {
+    replies: Arc<Mutex<VecDeque<Result<String>>>>,
+    calls: Arc<Mutex<Vec<ScriptedCall>>>,
+}
+
+impl ScriptedTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Queue raw stdout for the next query
+    pub fn reply(self, stdout: impl Into<String>) -> Self {
+        self.replies.lock().unwrap().push_back(Ok(stdout.into()));
+        self
+    }
+    
+    /// Queue a failure for the next query
+    pub fn fail(self, error: Error) -> Self {
+        self.replies.lock().unwrap().push_back(Err(error));
+        self
+    }
+    
+    /// Queries received so far
+    pub fn calls(&self) -> Vec<ScriptedCall> {
+        self.calls.lock().unwrap().clone()
+    }
+    
+    fn next_reply(&self, config: &Config, query: &str) -> Result<String> {
+        self.calls.lock().unwrap().push(ScriptedCall {
+            config: config.clone(),
+            query: query.to_string(),
+        });
+        self.replies
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| Err(Error::ProcessError("No scripted reply left".to_string())))
+    }
+}
+
+#[async_trait]
+impl Transport for ScriptedTransport {
+    async fn execute(&self, config: &Config, query: &str, cancel: CancellationToken) -> Result<String> {
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        self.next_reply(config, query)
+    }
+    
+    async fn stream(
+        &self,
+        config: &Config,
+        query: &str,
+        _cancel: CancellationToken,
+    ) -> Result<mpsc::Receiver<Result<String>>> {
+        let stdout = self.next_reply(config, query)?;
+        let lines: Vec<String> = stdout
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(String::from)
+            .collect();
+        
+        let (tx, rx) = mpsc::channel(lines.len().max(1));
+        for line in lines {
+            let _ = tx.try_send(Ok(line));
+        }
+        Ok(rx)
+    }
+}
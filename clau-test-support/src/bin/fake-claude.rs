@@ -0,0 +1,304 @@
+//! A fake `claude` CLI for tests. See the `clau-test-support` crate docs.
+
+use clau_test_support::{Event, Invocation, Scenario, PID_LOG, SCENARIO_ENV, SCENARIO_FILE};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::process::exit;
+use std::time::Duration;
+
+/// Flags that never take a value
+const BOOLEAN_FLAGS: &[&str] = &["-p", "--print", "--verbose", "--version", "--help"];
+
+/// Flags listed by `--help`
+const HELP_FLAGS: &[(&str, &str)] = &[
+    ("-p, --print", "Print response and exit"),
+    ("--output-format <format>", "Output format: text, json or stream-json"),
+    ("--input-format <format>", "Input format: text or stream-json"),
+    ("--verbose", "Verbose output"),
+    ("--system-prompt <prompt>", "System prompt"),
+    ("--model <model>", "Model for the session"),
+    ("--mcp-config <file>", "MCP server configuration"),
+    ("--allowedTools, --allowed-tools <tools...>", "Tools to allow"),
+    ("--max-tokens <n>", "Maximum output tokens"),
+    ("--add-dir <directories...>", "Additional directories to allow tool access to"),
+    ("--resume <session>", "Resume a conversation"),
+    ("-v, --version", "Output the version number"),
+    ("-h, --help", "Display help"),
+];
+
+struct Args {
+    raw: Vec<String>,
+    output_format: String,
+    input_format: String,
+    prompt: Option<String>,
+}
+
+fn parse_args() -> Args {
+    let raw: Vec<String> = std::env::args().skip(1).collect();
+    let mut output_format = "text".to_string();
+    let mut input_format = "text".to_string();
+    let mut prompt = None;
+    
+    let mut iter = raw.iter();
+    while let Some(arg) = iter.next() {
+        if BOOLEAN_FLAGS.contains(&arg.as_str()) {
+            continue;
+        }
+        if arg.starts_with('-') {
+            let value = iter.next().cloned().unwrap_or_default();
+            match arg.as_str() {
+                "--output-format" => output_format = value,
+                "--input-format" => input_format = value,
+                _ => {}
+            }
+            continue;
+        }
+        prompt = Some(arg.clone());
+    }
+    
+    Args {
+        raw,
+        output_format,
+        input_format,
+        prompt,
+    }
+}
+
+fn scenario_path() -> PathBuf {
+    std::env::var_os(SCENARIO_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(SCENARIO_FILE))
+}
+
+fn load_scenario() -> Scenario {
+    let path = scenario_path();
+    
+    let data = match std::fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("fake-claude: cannot read scenario {}: {}", path.display(), e);
+            exit(2);
+        }
+    };
+    match serde_json::from_str(&data) {
+        Ok(scenario) => scenario,
+        Err(e) => {
+            eprintln!("fake-claude: invalid scenario {}: {}", path.display(), e);
+            exit(2);
+        }
+    }
+}
+
+fn log_invocation(scenario: &Scenario, args: &Args) {
+    let Some(path) = &scenario.invocation_log else {
+        return;
+    };
+    let invocation = Invocation {
+        args: args.raw.clone(),
+        prompt: args.prompt.clone(),
+        cwd: std::env::current_dir().ok(),
+        env: std::env::vars().collect(),
+    };
+    let line = serde_json::to_string(&invocation).expect("invocation serializes");
+    let file = std::fs::OpenOptions::new().create(true).append(true).open(path);
+    if let Ok(mut file) = file {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+/// Append `pid` to the pid log in the working directory
+fn log_pid(pid: u32) {
+    let file = std::fs::OpenOptions::new().create(true).append(true).open(PID_LOG);
+    if let Ok(mut file) = file {
+        let _ = writeln!(file, "{}", pid);
+    }
+}
+
+fn emit(value: Value) {
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{}", value);
+    let _ = stdout.flush();
+}
+
+fn usage() -> Value {
+    json!({
+        "input_tokens": 10,
+        "output_tokens": 5,
+        "cache_creation_input_tokens": 0,
+        "cache_read_input_tokens": 0,
+    })
+}
+
+fn assistant(scenario: &Scenario, content: Value) -> Value {
+    json!({
+        "type": "assistant",
+        "message": {
+            "id": "msg_fake",
+            "type": "message",
+            "role": "assistant",
+            "model": scenario.model,
+            "content": [content],
+            "stop_reason": null,
+            "usage": usage(),
+        },
+        "parent_tool_use_id": null,
+        "session_id": scenario.session_id,
+    })
+}
+
+fn result(scenario: &Scenario) -> Value {
+    json!({
+        "type": "result",
+        "subtype": scenario.subtype,
+        "is_error": scenario.is_error,
+        "duration_ms": scenario.duration_ms,
+        "duration_api_ms": scenario.duration_ms,
+        "num_turns": scenario.num_turns,
+        "result": scenario.result_text(),
+        "session_id": scenario.session_id,
+        "cost_usd": scenario.cost_usd,
+        "total_cost": scenario.cost_usd,
+        "total_cost_usd": scenario.cost_usd,
+        "usage": usage(),
+    })
+}
+
+/// Play one turn of the scenario; returns the exit code to use
+fn play_turn(scenario: &Scenario, format: &str) -> i32 {
+    let stream = format == "stream-json";
+    
+    for (index, event) in scenario.events.iter().enumerate() {
+        match event {
+            Event::Text { text } if stream => {
+                emit(assistant(scenario, json!({"type": "text", "text": text})));
+            }
+            Event::ToolUse { name, input, result } if stream => {
+                let id = format!("toolu_fake_{}", index);
+                emit(assistant(scenario, json!({
+                    "type": "tool_use",
+                    "id": id,
+                    "name": name,
+                    "input": input,
+                })));
+                emit(json!({
+                    "type": "user",
+                    "message": {
+                        "role": "user",
+                        "content": [{
+                            "type": "tool_result",
+                            "tool_use_id": id,
+                            "content": result,
+                        }],
+                    },
+                    "parent_tool_use_id": null,
+                    "session_id": scenario.session_id,
+                }));
+            }
+            Event::Text { .. } | Event::ToolUse { .. } => {}
+            Event::Delay { ms } => std::thread::sleep(Duration::from_millis(*ms)),
+            Event::Stderr { text } => eprintln!("{}", text),
+            Event::Raw { line } => {
+                let mut stdout = io::stdout().lock();
+                let _ = writeln!(stdout, "{}", line);
+                let _ = stdout.flush();
+            }
+            Event::Spawn { program, args } => {
+                let child = std::process::Command::new(program)
+                    .args(args)
+                    .stdin(std::process::Stdio::null())
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .spawn();
+                match child {
+                    Ok(child) => log_pid(child.id()),
+                    Err(e) => eprintln!("fake-claude: cannot spawn {}: {}", program, e),
+                }
+            }
+        }
+    }
+    
+    if scenario.exit_code != 0 {
+        if let Some(stderr) = &scenario.stderr {
+            eprintln!("{}", stderr);
+        }
+        return scenario.exit_code;
+    }
+    
+    match format {
+        "json" | "stream-json" => emit(result(scenario)),
+        _ => println!("{}", scenario.result_text()),
+    }
+    0
+}
+
+fn main() {
+    let mut args = parse_args();
+    
+    // Probes work without a scenario so they never fail
+    if args.raw.iter().any(|a| a == "--version" || a == "--help") {
+        let scenario = std::fs::read_to_string(scenario_path())
+            .ok()
+            .and_then(|data| serde_json::from_str::<Scenario>(&data).ok())
+            .unwrap_or_default();
+        
+        if args.raw.iter().any(|a| a == "--version") {
+            println!("{} (Claude Code)", scenario.version);
+        } else {
+            println!("Usage: claude [options] [prompt]\n\nOptions:");
+            for (flags, description) in HELP_FLAGS {
+                let hidden = scenario.unsupported_flags.iter().any(|f| flags.contains(f.as_str()));
+                if !hidden {
+                    println!("  {:<45} {}", flags, description);
+                }
+            }
+        }
+        return;
+    }
+    
+    if args.prompt.is_none() && args.input_format == "text" {
+        // Like `claude -p`, take the prompt from stdin when none is passed
+        let mut prompt = String::new();
+        if io::stdin().lock().read_to_string(&mut prompt).is_ok() && !prompt.is_empty() {
+            args.prompt = Some(prompt);
+        }
+    }
+    
+    let scenario = load_scenario();
+    log_invocation(&scenario, &args);
+    if scenario.events.iter().any(|event| matches!(event, Event::Spawn { .. })) {
+        log_pid(std::process::id());
+    }
+    
+    let stream = args.output_format == "stream-json";
+    if stream {
+        emit(json!({
+            "type": "system",
+            "subtype": "init",
+            "session_id": scenario.session_id,
+            "model": scenario.model,
+            "tools": ["Bash", "Read", "Write"],
+            "cwd": std::env::current_dir().unwrap_or_default(),
+        }));
+    }
+    
+    if args.input_format == "stream-json" {
+        // Interactive mode: play a turn for every user message on stdin
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            let is_user = serde_json::from_str::<Value>(&line)
+                .map(|v| v.get("type").and_then(Value::as_str) == Some("user"))
+                .unwrap_or(false);
+            if !is_user {
+                continue;
+            }
+            let code = play_turn(&scenario, &args.output_format);
+            if code != 0 {
+                exit(code);
+            }
+        }
+        return;
+    }
+    
+    exit(play_turn(&scenario, &args.output_format));
+}
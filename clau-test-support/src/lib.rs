@@ -0,0 +1,302 @@
+//! Test support for clau.rs
+//! 
+//! This crate ships `fake-claude`, a stand-in for the Claude Code CLI that
+//! mimics `claude -p` for the `text`, `json` and `stream-json` output formats.
+//! What it prints is driven by a [`Scenario`] file, so tests can exercise the
+//! SDK end to end without a logged-in CLI or network access.
+//! 
+//! The binary looks for its scenario in the file named by the
+//! `FAKE_CLAUDE_SCENARIO` environment variable, falling back to
+//! `.fake-claude.json` in its working directory. [`Scenario::install`] writes
+//! the scenario into a fresh directory that can be used as the query's
+//! working directory, which keeps concurrently running tests apart.
+//! 
+//! ```rust,no_run
+//! use clau::{Client, StreamFormat};
+//! use clau_test_support::Scenario;
+//! 
+//! # #[tokio::main]
+//! # async fn main() -> clau::Result<()> {
+//! let scenario = Scenario::new()
+//!     .tool_use("Bash", serde_json::json!({"command": "ls"}), "Cargo.toml")
+//!     .text("There is one file.")
+//!     .install()?;
+//! 
+//! let client = Client::builder()
+//!     .claude_binary("target/debug/fake-claude")
+//!     .stream_format(StreamFormat::StreamJson)
+//!     .build();
+//! 
+//! let answer = client.query("List files").working_dir(scenario.path()).send().await?;
+//! assert_eq!(answer, "There is one file.");
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Environment variable naming the scenario file
+pub const SCENARIO_ENV: &str = "FAKE_CLAUDE_SCENARIO";
+
+/// Scenario file name looked up in the working directory
+pub const SCENARIO_FILE: &str = ".fake-claude.json";
+
+/// File in an installed scenario directory that records every invocation
+pub const INVOCATION_LOG: &str = "invocations.jsonl";
+
+/// File in the working directory that records the pids of the fake CLI and
+/// the subprocesses it spawns, one per line
+pub const PID_LOG: &str = "pids.txt";
+
+/// What the fake CLI does for each prompt it receives
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    /// Reported by `--version`
+    pub version: String,
+    pub session_id: String,
+    pub model: String,
+    /// Emitted in order before the final result
+    pub events: Vec<Event>,
+    /// Final result text; defaults to the concatenated `text` events
+    pub result: Option<String>,
+    /// Result subtype, e.g. `success` or `error_max_turns`
+    pub subtype: String,
+    pub is_error: bool,
+    pub num_turns: u32,
+    pub cost_usd: f64,
+    pub duration_ms: u64,
+    /// Exit code; anything but 0 skips the result and exits after the events
+    pub exit_code: i32,
+    /// Written to stderr before a non-zero exit
+    pub stderr: Option<String>,
+    /// Append each invocation's arguments here as a JSON line
+    pub invocation_log: Option<PathBuf>,
+    /// Flags left out of `--help`, to mimic an older CLI
+    pub unsupported_flags: Vec<String>,
+}
+
+/// One step of a [`Scenario`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// Assistant text
+    Text { text: String },
+    /// An assistant tool call followed by the tool's result
+    ToolUse {
+        name: String,
+        #[serde(default)]
+        input: Value,
+        #[serde(default)]
+        result: String,
+    },
+    /// Sleep before continuing
+    Delay { ms: u64 },
+    /// A line on stderr
+    Stderr { text: String },
+    /// A line written to stdout verbatim, whatever the output format
+    Raw { line: String },
+    /// Start a subprocess and leave it running, like a tool invocation
+    Spawn {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+/// A single recorded run of the fake CLI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invocation {
+    pub args: Vec<String>,
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    /// The process environment
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            version: "1.0.0".to_string(),
+            session_id: "fake-session".to_string(),
+            model: "claude-fake".to_string(),
+            events: Vec::new(),
+            result: None,
+            subtype: "success".to_string(),
+            is_error: false,
+            num_turns: 1,
+            cost_usd: 0.0,
+            duration_ms: 1,
+            exit_code: 0,
+            stderr: None,
+            invocation_log: None,
+            unsupported_flags: Vec::new(),
+        }
+    }
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = version.into();
+        self
+    }
+    
+    pub fn session_id(mut self, session_id: impl Into<String>) -> Self {
+        self.session_id = session_id.into();
+        self
+    }
+    
+    pub fn cost_usd(mut self, cost_usd: f64) -> Self {
+        self.cost_usd = cost_usd;
+        self
+    }
+    
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.events.push(Event::Text { text: text.into() });
+        self
+    }
+    
+    pub fn tool_use(mut self, name: impl Into<String>, input: Value, result: impl Into<String>) -> Self {
+        self.events.push(Event::ToolUse {
+            name: name.into(),
+            input,
+            result: result.into(),
+        });
+        self
+    }
+    
+    pub fn delay_ms(mut self, ms: u64) -> Self {
+        self.events.push(Event::Delay { ms });
+        self
+    }
+    
+    /// Start `program` in the background; its pid goes to [`PID_LOG`]
+    pub fn spawn<I, S>(mut self, program: impl Into<String>, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.events.push(Event::Spawn {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+        });
+        self
+    }
+    
+    pub fn raw(mut self, line: impl Into<String>) -> Self {
+        self.events.push(Event::Raw { line: line.into() });
+        self
+    }
+    
+    /// Leave `flag` out of the `--help` output
+    pub fn without_flag(mut self, flag: impl Into<String>) -> Self {
+        self.unsupported_flags.push(flag.into());
+        self
+    }
+    
+    /// Finish with an error result such as `error_max_turns`
+    pub fn error_result(mut self, subtype: impl Into<String>) -> Self {
+        self.subtype = subtype.into();
+        self.is_error = true;
+        self
+    }
+    
+    /// Exit with `code` after the events, writing `stderr` first
+    pub fn exit_with(mut self, code: i32, stderr: impl Into<String>) -> Self {
+        self.exit_code = code;
+        self.stderr = Some(stderr.into());
+        self
+    }
+    
+    /// Final result text
+    pub fn result_text(&self) -> String {
+        match &self.result {
+            Some(result) => result.clone(),
+            None => self.events
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+    
+    /// Write the scenario as JSON
+    pub fn write_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, data)
+    }
+    
+    /// Write the scenario into a new temporary directory
+    /// 
+    /// Use the directory as the query's working directory. Invocations are
+    /// logged inside it and can be read back with [`ScenarioDir::invocations`].
+    pub fn install(mut self) -> io::Result<ScenarioDir> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        
+        let dir = std::env::temp_dir().join(format!(
+            "fake-claude-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst),
+        ));
+        std::fs::create_dir_all(&dir)?;
+        
+        self.invocation_log = Some(dir.join(INVOCATION_LOG));
+        self.write_to(dir.join(SCENARIO_FILE))?;
+        Ok(ScenarioDir { path: dir })
+    }
+}
+
+/// A temporary directory holding an installed scenario, removed on drop
+#[derive(Debug)]
+pub struct ScenarioDir {
+    path: PathBuf,
+}
+
+impl ScenarioDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// Invocations the fake CLI has logged so far
+    pub fn invocations(&self) -> io::Result<Vec<Invocation>> {
+        let data = match std::fs::read_to_string(self.path.join(INVOCATION_LOG)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        data.lines()
+            .map(|line| serde_json::from_str(line).map_err(io::Error::from))
+            .collect()
+    }
+}
+
+impl ScenarioDir {
+    /// Pids the fake CLI has recorded so far: its own, then those of the
+    /// subprocesses it spawned
+    pub fn pids(&self) -> Vec<u32> {
+        std::fs::read_to_string(self.path.join(PID_LOG))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect()
+    }
+}
+
+impl Drop for ScenarioDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,869 @@
+use clau::{Client, Error, Message, StreamFormat};
+use clau_test_support::Scenario;
+use futures::StreamExt;
+
+fn client(format: StreamFormat) -> Client {
+    Client::builder()
+        .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+        .stream_format(format)
+        .timeout_secs(10)
+        .build()
+}
+
+#[tokio::test]
+async fn test_text_format() {
+    let scenario = Scenario::new().text("Hello from the fake").install().unwrap();
+    
+    let answer = client(StreamFormat::Text)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .send()
+        .await
+        .unwrap();
+    
+    assert_eq!(answer, "Hello from the fake");
+    
+    let invocations = scenario.invocations().unwrap();
+    assert_eq!(invocations.len(), 1);
+    assert_eq!(invocations[0].prompt.as_deref(), Some("Hi"));
+}
+
+#[tokio::test]
+async fn test_json_format_metadata() {
+    let scenario = Scenario::new()
+        .session_id("json-session")
+        .text("4")
+        .install()
+        .unwrap();
+    
+    let response = client(StreamFormat::Json)
+        .query("What is 2 + 2?")
+        .working_dir(scenario.path())
+        .send_full()
+        .await
+        .unwrap();
+    
+    assert_eq!(response.content, "4");
+    let metadata = response.metadata.unwrap();
+    assert_eq!(metadata.session_id, "json-session");
+    assert_eq!(metadata.schema, Some(clau::ResultSchema::V2));
+}
+
+#[tokio::test]
+async fn test_stream_json_tool_use() {
+    let scenario = Scenario::new()
+        .tool_use("Bash", serde_json::json!({"command": "ls"}), "Cargo.toml")
+        .text("One file.")
+        .install()
+        .unwrap();
+    
+    let messages: Vec<Message> = client(StreamFormat::StreamJson)
+        .query("List files")
+        .working_dir(scenario.path())
+        .stream()
+        .await
+        .unwrap()
+        .map(|m| m.unwrap())
+        .collect()
+        .await;
+    
+    assert!(matches!(messages.first(), Some(Message::Init { .. })));
+    let tool_use_id = messages.iter().find_map(|m| match m {
+        Message::Tool { name, tool_use_id, .. } if name == "Bash" => Some(tool_use_id),
+        _ => None,
+    });
+    assert!(tool_use_id.is_some_and(|id| id.starts_with("toolu_")));
+    assert!(messages.iter().any(|m| matches!(
+        m,
+        Message::ToolResult { tool_name, tool_use_id: id, .. } if tool_name.is_empty() && Some(id) == tool_use_id
+    )));
+    assert!(matches!(messages.last(), Some(Message::Result { .. })));
+}
+
+#[tokio::test]
+async fn test_non_zero_exit() {
+    let scenario = Scenario::new()
+        .exit_with(1, "Invalid API key")
+        .install()
+        .unwrap();
+    
+    let err = client(StreamFormat::Json)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .send()
+        .await
+        .unwrap_err();
+    
+    assert!(matches!(err, Error::AuthenticationFailed(_)), "{:?}", err);
+    assert!(err.to_string().contains("Invalid API key"), "{}", err);
+    assert!(!err.is_retryable());
+}
+
+#[tokio::test]
+async fn test_failure_context() {
+    let scenario = Scenario::new()
+        .raw("partial output")
+        .exit_with(3, "unknown option '--bogus'")
+        .install()
+        .unwrap();
+    
+    let config = clau::Config::builder()
+        .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+        .system_prompt("Top secret instructions")
+        .working_dir(scenario.path())
+        .build();
+    let err = Client::new(config).query("Private question").send().await.unwrap_err();
+    
+    let Error::ProcessFailed(failure) = err else {
+        panic!("expected ProcessFailed, got {:?}", err);
+    };
+    assert_eq!(failure.exit_code, Some(3));
+    assert_eq!(failure.signal, None);
+    assert!(failure.stderr.contains("--bogus"));
+    assert!(failure.stdout.contains("partial output"));
+    assert!(failure.command.contains("--system-prompt <redacted>"), "{}", failure.command);
+    assert!(!failure.command.contains("Top secret"));
+    assert!(!failure.command.contains("Private question"));
+}
+
+#[tokio::test]
+async fn test_rate_limit_from_stderr() {
+    let scenario = Scenario::new()
+        .exit_with(1, "API Error: 429 rate_limit_error, retry after 12 seconds")
+        .install()
+        .unwrap();
+    
+    let err = client(StreamFormat::Text)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .send()
+        .await
+        .unwrap_err();
+    
+    assert!(err.is_retryable());
+    assert!(
+        matches!(err, Error::RateLimited { retry_after: Some(wait) } if wait.as_secs() == 12),
+        "{:?}",
+        err,
+    );
+}
+
+#[tokio::test]
+async fn test_error_result_subtype() {
+    let scenario = Scenario::new()
+        .text("Still working")
+        .cost_usd(0.25)
+        .error_result("error_max_turns")
+        .install()
+        .unwrap();
+    
+    // The partial text in `result` must not come back as the answer
+    let err = client(StreamFormat::Json)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .send_full()
+        .await
+        .unwrap_err();
+    match err {
+        Error::MaxTurnsReached(failure) => {
+            assert_eq!((failure.num_turns, failure.cost_usd), (1, Some(0.25)));
+            assert_eq!(failure.session_id, "fake-session");
+            assert_eq!(failure.result, "Still working");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    
+    let mut stream = client(StreamFormat::StreamJson)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .stream()
+        .await
+        .unwrap();
+    let mut items = Vec::new();
+    while let Some(item) = stream.next().await {
+        items.push(item);
+    }
+    
+    // The result message is still delivered, followed by the typed error
+    let (last, rest) = items.split_last().unwrap();
+    assert!(matches!(last, Err(Error::MaxTurnsReached(_))), "{:?}", last);
+    assert!(matches!(rest.last(), Some(Ok(Message::Result { failure: Some(_), .. }))));
+    
+    let collected = client(StreamFormat::StreamJson)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .stream()
+        .await
+        .unwrap()
+        .collect_full_response()
+        .await;
+    assert!(matches!(collected, Err(Error::MaxTurnsReached(_))), "{:?}", collected);
+}
+
+#[tokio::test]
+async fn test_classified_error_result_keeps_run() {
+    let mut scenario = Scenario::new().text("API Error: 529 overloaded_error").cost_usd(0.7);
+    scenario.is_error = true;
+    let scenario = scenario.install().unwrap();
+    
+    let err = client(StreamFormat::Json)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .send_full()
+        .await
+        .unwrap_err();
+    assert!(matches!(err.cause(), Error::Overloaded(_)), "{:?}", err);
+    assert!(err.is_retryable());
+    let failure = err.run_failure().unwrap();
+    assert_eq!((failure.num_turns, failure.cost_usd), (1, Some(0.7)));
+    assert_eq!(failure.session_id, "fake-session");
+}
+
+/// Wait until the fake CLI and the subprocess it spawned have both started
+#[cfg(target_os = "linux")]
+async fn spawned_pids(scenario: &clau_test_support::ScenarioDir) -> Vec<u32> {
+    for _ in 0..100 {
+        let pids = scenario.pids();
+        if pids.len() == 2 {
+            return pids;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    panic!("fake CLI never spawned its subprocess");
+}
+
+/// Whether `pid` still runs; zombies waiting to be reaped count as gone
+#[cfg(target_os = "linux")]
+async fn is_running(pid: u32) -> bool {
+    for _ in 0..50 {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+        let state = stat.rsplit(')').next().and_then(|rest| rest.split_whitespace().next());
+        if matches!(state, None | Some("Z") | Some("X")) {
+            return false;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    true
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_cancel_kills_process_tree() {
+    use clau::CancellationToken;
+    
+    let scenario = Scenario::new()
+        .spawn("sleep", ["30"])
+        .delay_ms(30_000)
+        .text("never")
+        .install()
+        .unwrap();
+    let token = CancellationToken::new();
+    let query = tokio::spawn(
+        client(StreamFormat::Json)
+            .query("Hi")
+            .working_dir(scenario.path())
+            .timeout_secs(60)
+            .cancel_token(token.clone())
+            .send_full(),
+    );
+    
+    let pids = spawned_pids(&scenario).await;
+    token.cancel();
+    let result = query.await.unwrap();
+    assert!(matches!(result, Err(Error::Cancelled)), "{:?}", result);
+    for pid in pids {
+        assert!(!is_running(pid).await, "pid {} survived cancellation", pid);
+    }
+    
+    // Dropping a stream has the same effect
+    let scenario = Scenario::new()
+        .spawn("sleep", ["30"])
+        .delay_ms(30_000)
+        .install()
+        .unwrap();
+    let stream = client(StreamFormat::StreamJson)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .timeout_secs(60)
+        .stream()
+        .await
+        .unwrap();
+    let pids = spawned_pids(&scenario).await;
+    drop(stream);
+    for pid in pids {
+        assert!(!is_running(pid).await, "pid {} survived dropping the stream", pid);
+    }
+}
+
+#[tokio::test]
+async fn test_non_ascii_output_over_tail_limit() {
+    // Enough multi-byte text that trimming the kept stdout tail cuts through characters
+    let lines: Vec<String> = (0..4).map(|i| format!("{}{}", "é".repeat(3000), "a".repeat(i))).collect();
+    let scenario = lines.iter().fold(Scenario::new(), |scenario, line| scenario.text(line)).install().unwrap();
+    
+    let answer = client(StreamFormat::StreamJson)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .stream()
+        .await
+        .unwrap()
+        .collect_full_response()
+        .await
+        .unwrap();
+    assert_eq!(answer, lines.concat());
+}
+
+#[tokio::test]
+async fn test_delay_hits_timeout() {
+    let scenario = Scenario::new().delay_ms(5_000).text("too late").install().unwrap();
+    
+    let err = client(StreamFormat::Text)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .timeout_secs(1)
+        .send()
+        .await
+        .unwrap_err();
+    
+    let Error::QueryTimedOut(timeout) = err else {
+        panic!("expected QueryTimedOut, got {:?}", err);
+    };
+    assert_eq!((timeout.kind, timeout.secs), (clau::TimeoutKind::Total, 1));
+    assert!(timeout.partial.is_empty());
+}
+
+#[tokio::test]
+async fn test_idle_timeout_returns_partial_messages() {
+    let scenario = Scenario::new()
+        .text("first")
+        .delay_ms(5_000)
+        .text("second")
+        .install()
+        .unwrap();
+    
+    let err = client(StreamFormat::StreamJson)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .idle_timeout_secs(1)
+        .send()
+        .await
+        .unwrap_err();
+    
+    let Error::QueryTimedOut(timeout) = err else {
+        panic!("expected QueryTimedOut, got {:?}", err);
+    };
+    assert_eq!((timeout.kind, timeout.secs), (clau::TimeoutKind::Idle, 1));
+    assert!(matches!(timeout.partial[0], Message::Init { .. }));
+    assert!(matches!(timeout.partial[1], Message::Assistant { ref content, .. } if content == "first"));
+    assert_eq!(timeout.partial.len(), 2);
+    
+    // Streams end with the same error after the messages already delivered
+    let mut stream = client(StreamFormat::StreamJson)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .idle_timeout_secs(1)
+        .stream()
+        .await
+        .unwrap();
+    let mut items = Vec::new();
+    while let Some(item) = stream.next().await {
+        items.push(item);
+    }
+    assert_eq!(items.len(), 3);
+    assert!(matches!(items[2], Err(Error::QueryTimedOut(ref t)) if t.partial.len() == 2));
+    
+    // A stream keeps only as much recent output as the limits allow
+    let mut stream = Client::builder()
+        .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+        .stream_format(StreamFormat::StreamJson)
+        .output_limits(clau::OutputLimits::default().max_raw_messages(1))
+        .build()
+        .query("Hi")
+        .working_dir(scenario.path())
+        .idle_timeout_secs(1)
+        .stream()
+        .await
+        .unwrap();
+    let mut last = None;
+    while let Some(item) = stream.next().await {
+        last = Some(item);
+    }
+    let Some(Err(Error::QueryTimedOut(timeout))) = last else {
+        panic!("expected QueryTimedOut, got {:?}", last);
+    };
+    assert!(matches!(timeout.partial[..], [Message::Assistant { ref content, .. }] if content == "first"));
+}
+
+#[tokio::test]
+async fn test_idle_timeout_resets_on_output() {
+    let scenario = Scenario::new()
+        .text("one ")
+        .delay_ms(700)
+        .text("two ")
+        .delay_ms(700)
+        .text("three")
+        .install()
+        .unwrap();
+    
+    let answer = client(StreamFormat::StreamJson)
+        .query("Hi")
+        .working_dir(scenario.path())
+        .idle_timeout_secs(1)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(answer, "one two three");
+    
+    // JSON prints nothing until the end, so the idle timeout does not apply
+    let answer = client(StreamFormat::Json).query("Hi").working_dir(scenario.path()).idle_timeout_secs(1).send().await;
+    assert_eq!(answer.unwrap(), "one two three");
+    
+    // A total deadline would cut the run short; with none, only the idle timeout applies
+    let err = client(StreamFormat::StreamJson).query("Hi").working_dir(scenario.path()).timeout_secs(1).send().await.unwrap_err();
+    assert!(matches!(err, Error::QueryTimedOut(ref t) if t.kind == clau::TimeoutKind::Total), "{:?}", err);
+    
+    let idle_only = Client::builder()
+        .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+        .no_timeout()
+        .idle_timeout_secs(1)
+        .build();
+    let answer = idle_only.query("Hi").working_dir(scenario.path()).stream().await.unwrap().collect_full_response().await.unwrap();
+    assert_eq!(answer, "one two three");
+}
+
+#[tokio::test]
+async fn test_session_resume_is_passed() {
+    let scenario = Scenario::new()
+        .session_id("next-session")
+        .text("ok")
+        .install()
+        .unwrap();
+    let client = client(StreamFormat::Json);
+    let handle = clau::SessionId::new("first-session");
+    
+    for _ in 0..2 {
+        client
+            .query("Continue")
+            .session(handle.clone())
+            .working_dir(scenario.path())
+            .send()
+            .await
+            .unwrap();
+    }
+    
+    let invocations = scenario.invocations().unwrap();
+    let resumed: Vec<_> = invocations
+        .iter()
+        .map(|i| {
+            let at = i.args.iter().position(|a| a == "--resume").unwrap();
+            i.args[at + 1].clone()
+        })
+        .collect();
+    assert_eq!(resumed, ["first-session", "next-session"]);
+}
+
+#[tokio::test]
+async fn test_failed_run_session_is_resumed() {
+    let scenario = Scenario::new()
+        .session_id("failed-session")
+        .text("partial answer")
+        .error_result("error_max_turns")
+        .install()
+        .unwrap();
+    let client = client(StreamFormat::Json);
+    let handle = clau::SessionId::new("first-session");
+    
+    for _ in 0..2 {
+        let err = client
+            .query("Continue")
+            .session(handle.clone())
+            .working_dir(scenario.path())
+            .send_full()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::MaxTurnsReached(_)), "{:?}", err);
+    }
+    
+    let resumed: Vec<_> = scenario
+        .invocations()
+        .unwrap()
+        .iter()
+        .map(|i| {
+            let at = i.args.iter().position(|a| a == "--resume").unwrap();
+            i.args[at + 1].clone()
+        })
+        .collect();
+    assert_eq!(resumed, ["first-session", "failed-session"]);
+}
+
+#[tokio::test]
+async fn test_interactive_session() {
+    let scenario = Scenario::new().text("pong").install().unwrap();
+    let config = clau::Config::builder()
+        .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+        .working_dir(scenario.path())
+        .build();
+    let client = Client::new(config);
+    
+    let mut session = client.interactive().await.unwrap();
+    assert_eq!(session.ask("ping").await.unwrap(), "pong");
+    assert_eq!(session.ask("ping again").await.unwrap(), "pong");
+    session.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_interactive_error_result() {
+    let scenario = Scenario::new()
+        .text("partial answer")
+        .error_result("error_during_execution")
+        .install()
+        .unwrap();
+    let config = clau::Config::builder()
+        .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+        .working_dir(scenario.path())
+        .build();
+    let client = Client::new(config);
+    
+    let mut session = client.interactive().await.unwrap();
+    for _ in 0..2 {
+        match session.ask("ping").await {
+            Err(Error::ExecutionError(failure)) => assert_eq!(failure.session_id, "fake-session"),
+            other => panic!("unexpected answer: {:?}", other),
+        }
+    }
+    session.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_min_cli_version() {
+    use clau::{CliVersion, Config, VersionPolicy};
+
+    let scenario = Scenario::new().version("1.2.3").text("ok").install().unwrap();
+    let config = |policy| {
+        Config::builder()
+            .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+            .working_dir(scenario.path())
+            .min_cli_version(CliVersion::new(2, 0, 0), policy)
+            .build()
+    };
+
+    let client = Client::new(config(VersionPolicy::Require));
+    assert_eq!(client.cli_version().await.unwrap(), CliVersion::new(1, 2, 3));
+    let err = client.send("Hi").await.unwrap_err();
+    assert!(matches!(err, Error::UnsupportedCliVersion { .. }), "{:?}", err);
+
+    let client = Client::new(config(VersionPolicy::Warn));
+    assert_eq!(client.send("Hi").await.unwrap(), "ok");
+}
+
+#[tokio::test]
+async fn test_cassette_record_round_trip() {
+    use clau::{CassetteTransport, CliVersion, Config, VersionPolicy};
+    
+    let scenario = Scenario::new().version("2.1.0").text("Recorded answer").install().unwrap();
+    let cassette = scenario.path().join("cassette.json");
+    let config = Config::builder()
+        .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+        .working_dir(scenario.path())
+        .stream_format(StreamFormat::StreamJson)
+        .min_cli_version(CliVersion::new(2, 0, 0), VersionPolicy::Require)
+        .build();
+    
+    // Recording runs the real CLI, version check included
+    let recorder = CassetteTransport::record(&cassette);
+    let client = Client::builder().config(config.clone()).transport(recorder.clone()).build();
+    assert_eq!(client.cli_version().await.unwrap(), CliVersion::new(2, 1, 0));
+    assert_eq!(client.send("Hi").await.unwrap(), "Recorded answer");
+    assert_eq!(recorder.cassette().interactions.len(), 1);
+    
+    // Replay serves the recording without spawning the CLI
+    let replay_config = Config { min_cli_version: None, ..config };
+    let client = Client::builder()
+        .config(replay_config)
+        .transport(CassetteTransport::replay(&cassette).unwrap())
+        .build();
+    let invocations = scenario.invocations().unwrap().len();
+    assert_eq!(client.send("Hi").await.unwrap(), "Recorded answer");
+    assert_eq!(scenario.invocations().unwrap().len(), invocations);
+}
+
+#[tokio::test]
+async fn test_missing_binary_lists_searched_paths() {
+    let client = Client::builder().claude_binary("/nonexistent/claude").build();
+
+    let err = client.send("Hi").await.unwrap_err();
+    assert!(matches!(err, Error::BinaryNotFound { .. }));
+    assert!(err.to_string().contains("/nonexistent/claude"));
+}
+
+#[tokio::test]
+async fn test_unsupported_flag_is_config_error() {
+    let scenario = Scenario::new().without_flag("--max-tokens").text("ok").install().unwrap();
+    let config = clau::Config::builder()
+        .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+        .working_dir(scenario.path())
+        .build();
+    let client = Client::new(config);
+
+    let capabilities = client.capabilities().await.unwrap();
+    assert!(capabilities.supports("--model"));
+    assert!(!capabilities.supports("--max-tokens"));
+
+    let err = client.query("Hi").max_tokens(100).send().await.unwrap_err();
+    assert!(matches!(err, Error::ConfigError(ref msg) if msg.contains("--max-tokens")), "{:?}", err);
+
+    // Nothing was spawned for the rejected query
+    assert_eq!(scenario.invocations().unwrap().len(), 0);
+    assert_eq!(client.query("Hi").send().await.unwrap(), "ok");
+}
+
+#[tokio::test]
+async fn test_stream_checks_stream_json_flags() {
+    let scenario = Scenario::new().without_flag("--verbose").text("ok").install().unwrap();
+    
+    let config = clau::Config::builder()
+        .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+        .working_dir(scenario.path())
+        .stream_format(StreamFormat::Text)
+        .build();
+    
+    // The client's text format needs no --verbose, but streaming does
+    let Err(err) = Client::new(config).query("Hi").stream().await else {
+        panic!("streaming without --verbose support should be rejected");
+    };
+    assert!(matches!(err, Error::ConfigError(ref msg) if msg.contains("--verbose")), "{:?}", err);
+    assert_eq!(scenario.invocations().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn test_prompt_over_stdin() {
+    use clau::PromptDelivery;
+    
+    for format in [StreamFormat::Text, StreamFormat::Json, StreamFormat::StreamJson] {
+        let scenario = Scenario::new().text("Got it").install().unwrap();
+        let config = clau::Config::builder()
+            .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+            .stream_format(format)
+            .prompt_delivery(PromptDelivery::Stdin)
+            .working_dir(scenario.path())
+            .build();
+        
+        let answer = Client::new(config).query("Secret prompt").send().await.unwrap();
+        assert_eq!(answer, "Got it");
+        
+        let invocations = scenario.invocations().unwrap();
+        assert_eq!(invocations[0].prompt.as_deref(), Some("Secret prompt"));
+        assert!(!invocations[0].args.iter().any(|arg| arg.contains("Secret")), "{:?}", format);
+    }
+}
+
+#[tokio::test]
+async fn test_large_prompt_switches_to_stdin() {
+    let scenario = Scenario::new().text("Read it").install().unwrap();
+    let prompt = "x".repeat(256 * 1024);
+    
+    let client = client(StreamFormat::StreamJson);
+    let mut stream = client.query(&prompt).working_dir(scenario.path()).stream().await.unwrap();
+    while let Some(message) = stream.next().await {
+        message.unwrap();
+    }
+    
+    let invocations = scenario.invocations().unwrap();
+    assert_eq!(invocations[0].prompt.as_deref().map(str::len), Some(prompt.len()));
+    assert!(invocations[0].args.iter().all(|arg| arg.len() < 1024));
+}
+
+#[tokio::test]
+async fn test_max_concurrency_caps_processes() {
+    let scenario = Scenario::new().delay_ms(150).text("done").install().unwrap();
+    let client = Client::builder()
+        .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+        .timeout_secs(10)
+        .max_concurrency(2)
+        .build();
+    // Probe the CLI up front so every query goes straight to the queue
+    client.capabilities().await.unwrap();
+    
+    let queries: Vec<_> = (0..6)
+        .map(|_| {
+            let client = client.clone();
+            let dir = scenario.path().to_path_buf();
+            tokio::spawn(async move { client.query("Hi").working_dir(dir).send().await })
+        })
+        .collect();
+    
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let metrics = client.queue_metrics().unwrap();
+    assert_eq!(metrics.max_concurrency, 2);
+    assert_eq!(metrics.running, 2);
+    assert_eq!(metrics.queued, 4);
+    
+    for query in queries {
+        assert_eq!(query.await.unwrap().unwrap(), "done");
+    }
+    
+    let metrics = client.queue_metrics().unwrap();
+    assert_eq!((metrics.running, metrics.queued, metrics.total_started), (0, 0, 6));
+    assert!(metrics.peak_queued >= 4);
+}
+
+#[tokio::test]
+async fn test_working_dir_add_dir_and_env() {
+    let scenario = Scenario::new().text("ok").install().unwrap();
+    let extra = std::env::temp_dir();
+    let client = Client::builder()
+        .config(
+            clau::Config::builder()
+                .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+                .env("CLAU_TEAM", "client")
+                .env("CLAU_KEEP", "yes")
+                .build(),
+        )
+        .build();
+    
+    client
+        .query("Hi")
+        .working_dir(scenario.path())
+        .add_dir(&extra)
+        .env("CLAU_TEAM", "query")
+        .env_remove("PATH")
+        .send()
+        .await
+        .unwrap();
+    
+    let invocation = &scenario.invocations().unwrap()[0];
+    assert_eq!(invocation.cwd.as_deref(), Some(scenario.path()));
+    let add_dir = invocation.args.iter().position(|arg| arg == "--add-dir").unwrap();
+    assert_eq!(invocation.args[add_dir + 1], extra.to_string_lossy());
+    assert_eq!(invocation.env.get("CLAU_TEAM").map(String::as_str), Some("query"));
+    assert_eq!(invocation.env.get("CLAU_KEEP").map(String::as_str), Some("yes"));
+    assert!(!invocation.env.contains_key("PATH"));
+    
+    client
+        .query("Hi")
+        .working_dir(scenario.path())
+        .env_clear()
+        .send()
+        .await
+        .unwrap();
+    
+    let invocation = &scenario.invocations().unwrap()[1];
+    let keys: Vec<_> = invocation.env.keys().map(String::as_str).collect();
+    assert_eq!(keys, ["CLAU_KEEP", "CLAU_TEAM"]);
+}
+
+#[tokio::test]
+async fn test_isolated_config_home() {
+    let scenario = Scenario::new().text("ok").install().unwrap();
+    let template = scenario.path().join("template");
+    std::fs::create_dir_all(template.join("commands")).unwrap();
+    std::fs::write(template.join("settings.json"), "{}").unwrap();
+    std::fs::write(template.join("commands/review.md"), "Review").unwrap();
+    
+    let homes = [scenario.path().join("teams/a"), scenario.path().join("teams/b")];
+    for home in &homes {
+        let client = Client::builder()
+            .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+            .config_home_from_template(home, &template)
+            .build();
+        client.query("Hi").working_dir(scenario.path()).send().await.unwrap();
+        
+        assert!(home.join("settings.json").is_file());
+        assert!(home.join("commands/review.md").is_file());
+    }
+    
+    let invocations = scenario.invocations().unwrap();
+    for (invocation, home) in invocations.iter().zip(&homes) {
+        let config_dir = invocation.env.get("CLAUDE_CONFIG_DIR").map(std::path::PathBuf::from);
+        assert_eq!(config_dir.as_ref(), Some(home));
+    }
+    
+    // An existing home is used as is
+    std::fs::remove_file(homes[0].join("settings.json")).unwrap();
+    let client = Client::builder()
+        .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+        .config_home_from_template(&homes[0], &template)
+        .build();
+    client.query("Hi").working_dir(scenario.path()).send().await.unwrap();
+    assert!(!homes[0].join("settings.json").exists());
+}
+
+#[tokio::test]
+async fn test_dry_run_reproduces_invocation() {
+    let scenario = Scenario::new().text("ok").install().unwrap();
+    let client = client(StreamFormat::Json);
+    let query = || {
+        client
+            .query("It's a \"quoted\" $prompt")
+            .working_dir(scenario.path())
+            .env("CLAU_TEAM", "a b")
+    };
+    
+    let command = query().dry_run().await.unwrap();
+    assert!(scenario.invocations().unwrap().is_empty());
+    assert!(!command.redacted().contains("$prompt"));
+    
+    query().send().await.unwrap();
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command.to_shell())
+        .stdout(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    
+    let invocations = scenario.invocations().unwrap();
+    assert_eq!(invocations.len(), 2);
+    assert_eq!(invocations[1].args, invocations[0].args);
+    assert_eq!(invocations[1].args, command.args);
+    assert_eq!(invocations[1].env.get("CLAU_TEAM").map(String::as_str), Some("a b"));
+}
+
+#[tokio::test]
+async fn test_output_limits() {
+    use clau::{OutputKind, OutputLimits, OverflowPolicy};
+    
+    let scenario = Scenario::new()
+        .tool_use("Bash", serde_json::json!({"command": "yes"}), "y\n".repeat(100_000))
+        .text("Stopped it.")
+        .install()
+        .unwrap();
+    let limited = |limits: OutputLimits| {
+        Client::builder()
+            .claude_binary(env!("CARGO_BIN_EXE_fake-claude"))
+            .stream_format(StreamFormat::StreamJson)
+            .output_limits(limits)
+            .build()
+    };
+    
+    let limits = OutputLimits::default().max_stdout_bytes(16 * 1024);
+    let err = limited(limits).query("Run yes").working_dir(scenario.path()).send().await.unwrap_err();
+    assert!(matches!(err, Error::OutputLimitExceeded { kind: OutputKind::Stdout, limit: 16384 }), "{:?}", err);
+    
+    let mut stream = limited(limits).query("Run yes").working_dir(scenario.path()).stream().await.unwrap();
+    let mut last = None;
+    while let Some(item) = stream.next().await {
+        last = Some(item);
+    }
+    assert!(matches!(last, Some(Err(Error::OutputLimitExceeded { kind: OutputKind::Stdout, .. }))));
+    
+    // Truncation drops the oversized tool output but keeps the answer and the result
+    let limits = limits.max_raw_messages(2).on_overflow(OverflowPolicy::Truncate);
+    let response = limited(limits).query("Run yes").working_dir(scenario.path()).send_full().await.unwrap();
+    assert_eq!(response.content, "Stopped it.");
+    let raw = response.raw_json.unwrap();
+    let types: Vec<_> = raw.as_array().unwrap().iter().map(|m| m["type"].as_str().unwrap()).collect();
+    assert_eq!(types, ["assistant", "result"]);
+    
+    let limits = OutputLimits::default().max_raw_messages(2);
+    let err = limited(limits).query("Run yes").working_dir(scenario.path()).send().await.unwrap_err();
+    assert!(matches!(err, Error::OutputLimitExceeded { kind: OutputKind::RawMessages, limit: 2 }));
+    
+    let noisy = Scenario::new().exit_with(1, "warning: ".repeat(10_000)).install().unwrap();
+    let limits = OutputLimits::default().max_stderr_bytes(1024);
+    let err = limited(limits).query("Hi").working_dir(noisy.path()).send().await.unwrap_err();
+    assert!(matches!(err, Error::OutputLimitExceeded { kind: OutputKind::Stderr, limit: 1024 }), "{:?}", err);
+    
+    let err = limited(limits.on_overflow(OverflowPolicy::Truncate)).query("Hi").working_dir(noisy.path()).send().await.unwrap_err();
+    assert!(matches!(err, Error::ProcessFailed(ref failure) if failure.stderr.len() <= 1024), "{:?}", err);
+}
@@ -29,7 +29,7 @@ async fn main() -> clau::Result<()> {
                 use std::io::{self, Write};
                 io::stdout().flush().unwrap();
             }
-            Message::Result { meta: _, stats } => {
+            Message::Result { stats, .. } => {
                 println!("\n\nConversation complete!");
                 println!("Total cost: ${:.4}", stats.total_cost_usd);
                 println!("Duration: {}ms", stats.total_duration_ms);
@@ -24,15 +24,20 @@
 
 // Re-export core types
 pub use clau_core::{
-    Config, Error, Result, Message, MessageType, MessageMeta,
+    Config, ConfigOverrides, Error, OutputKind, OutputLimits, OverflowPolicy, ProcessFailure, QueryTimeout, RunFailure, TimeoutKind, Result, Message, MessageType, MessageMeta,
+    CliMessage, SystemMessage, AssistantMessage, UserMessage, ResultMessage, ApiMessage, McpServerStatus,
+    ContentBlock, ToolResultContent,
     Session, SessionId, SessionManager,
     StreamFormat, ToolPermission, Cost,
-    ClaudeResponse, ResponseMetadata, TokenUsage,
+    ClaudeCliResponse, ClaudeResponse, ResponseMetadata, ResultSchema, TokenUsage,
+    CliVersion, PromptDelivery, RetryPolicy, VersionPolicy,
 };
 
 // Re-export runtime types
 pub use clau_runtime::{
-    Client, QueryBuilder, MessageStream,
+    Client, QueryBuilder, MessageStream, InteractiveSession, Capabilities,
+    CancellationToken, Transport, SubprocessTransport, ScriptedTransport, ScriptedCall,
+    CassetteMode, CassetteTransport, ClaudeCommand, QueueMetrics,
 };
 
 // Re-export MCP types when ready
@@ -16,6 +16,15 @@ fn test_config_builder() {
     assert_eq!(config.model, Some("claude-sonnet-4-20250514".to_string()));
     assert_eq!(config.stream_format, StreamFormat::Json);
     assert_eq!(config.max_tokens, Some(1000));
+    assert_eq!(config.timeout_secs, Some(30));
+
+    // No total deadline survives a round trip instead of reverting to the default
+    let config = Config::builder().no_timeout().idle_timeout_secs(60).build();
+    let restored: Config = serde_json::from_value(serde_json::to_value(&config).unwrap()).unwrap();
+    assert_eq!((restored.timeout_secs, restored.idle_timeout_secs), (None, Some(60)));
+    let mut json = serde_json::to_value(Config::default()).unwrap();
+    json.as_object_mut().unwrap().remove("timeout_secs");
+    assert_eq!(serde_json::from_value::<Config>(json).unwrap().timeout_secs, None);
 }
 
 #[test]
@@ -45,3 +54,610 @@ fn test_tool_permission_formatting() {
     let all = ToolPermission::All;
     assert_eq!(all.to_cli_format(), "*");
 }
+
+#[test]
+fn test_parse_cli_stream_json_lines() {
+    use clau::{Message, StreamFormat};
+    use clau_runtime::stream::MessageParser;
+
+    let parser = MessageParser::new(StreamFormat::StreamJson);
+
+    let init = r#"{"type":"system","subtype":"init","session_id":"abc","tools":["Bash"]}"#;
+    assert!(matches!(parser.parse_line(init).unwrap(), Some(Message::Init { .. })));
+
+    let assistant = r#"{"type":"assistant","session_id":"abc","message":{"content":[{"type":"text","text":"Hello"}]}}"#;
+    match parser.parse_line(assistant).unwrap() {
+        Some(Message::Assistant { content, meta }) => {
+            assert_eq!(content, "Hello");
+            assert_eq!(meta.session_id, "abc");
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    let result = r#"{"type":"result","subtype":"success","session_id":"abc","total_cost_usd":0.01,"duration_ms":1200,"num_turns":2}"#;
+    match parser.parse_line(result).unwrap() {
+        Some(Message::Result { stats, .. }) => {
+            assert_eq!(stats.total_messages, 2);
+            assert_eq!(stats.total_duration_ms, 1200);
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+#[test]
+fn test_cli_message_model() {
+    use clau::{CliMessage, ContentBlock, Message, ResultMessage, StreamFormat, ToolResultContent};
+    use clau_runtime::stream::MessageParser;
+
+    let parser = MessageParser::new(StreamFormat::StreamJson);
+
+    let init = r#"{"type":"system","subtype":"init","cwd":"/repo","session_id":"abc","tools":["Bash","Read"],"mcp_servers":[{"name":"docs","status":"connected"}],"model":"claude-sonnet-4-20250514","permissionMode":"default","apiKeySource":"none"}"#;
+    match parser.parse_cli_line(init).unwrap() {
+        Some(CliMessage::System(system)) => {
+            assert_eq!(system.tools, ["Bash", "Read"]);
+            assert_eq!(system.mcp_servers[0].status, "connected");
+            assert_eq!(system.permission_mode.as_deref(), Some("default"));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    let assistant = r#"{"type":"assistant","message":{"id":"msg_1","type":"message","role":"assistant","model":"claude-sonnet-4-20250514","content":[{"type":"thinking","thinking":"Look first.","signature":"sig"},{"type":"text","text":"Checking."},{"type":"tool_use","id":"toolu_1","name":"Bash","input":{"command":"ls"}},{"type":"server_tool_use","id":"srvtoolu_1","name":"web_search"}],"stop_reason":"tool_use","usage":{"input_tokens":3,"output_tokens":20}},"parent_tool_use_id":null,"session_id":"abc"}"#;
+    let Some(CliMessage::Assistant(message)) = parser.parse_cli_line(assistant).unwrap() else {
+        panic!("expected an assistant message");
+    };
+    assert!(matches!(&message.message.content[0], ContentBlock::Thinking { thinking, .. } if thinking == "Look first."));
+    assert_eq!(message.message.text(), "Checking.");
+    assert!(matches!(message.message.tool_uses().next(), Some(ContentBlock::ToolUse { name, .. }) if name == "Bash"));
+    assert!(matches!(&message.message.content[3], ContentBlock::Other(block) if block["name"] == "web_search"));
+    assert!(matches!(parser.parse_line(assistant).unwrap(), Some(Message::Assistant { content, .. }) if content == "Checking."));
+
+    let tool_result = r#"{"type":"user","message":{"role":"user","content":[{"tool_use_id":"toolu_1","type":"tool_result","content":[{"type":"text","text":"Cargo.toml"}],"is_error":false}]},"session_id":"abc"}"#;
+    let Some(CliMessage::User(user)) = parser.parse_cli_line(tool_result).unwrap() else {
+        panic!("expected a user message");
+    };
+    match &user.message.content[0] {
+        ContentBlock::ToolResult { content: Some(content @ ToolResultContent::Blocks(_)), is_error, .. } => {
+            assert_eq!(content.text(), "Cargo.toml");
+            assert_eq!(*is_error, Some(false));
+        }
+        other => panic!("unexpected block: {:?}", other),
+    }
+
+    let error_result = r#"{"type":"result","subtype":"error_max_turns","is_error":false,"duration_ms":900,"num_turns":5,"session_id":"abc","cost_usd":0.2,"total_cost_usd":0.2}"#;
+    match parser.parse_cli_line(error_result).unwrap() {
+        Some(CliMessage::Result(result)) => {
+            assert_eq!(result.subtype, "error_max_turns");
+            assert_eq!(result.result, None);
+            assert_eq!(result.cost(), Some(0.2));
+            let older: ResultMessage = serde_json::from_str(r#"{"subtype":"success","total_cost":0.3}"#).unwrap();
+            assert_eq!(older.cost(), Some(0.3));
+            let failure = result.failure().unwrap();
+            assert_eq!((failure.num_turns, failure.session_id.as_str()), (5, "abc"));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    // Messages the SDK does not know are skipped rather than failing the stream
+    let unknown = r#"{"type":"stream_event","event":{"type":"message_start"},"session_id":"abc"}"#;
+    assert!(matches!(parser.parse_cli_line(unknown).unwrap(), Some(CliMessage::Other(_))));
+    assert!(parser.parse_line(unknown).unwrap().is_none());
+    let compact = r#"{"type":"system","subtype":"compact_boundary","session_id":"abc"}"#;
+    assert!(matches!(parser.parse_line(compact).unwrap(), Some(Message::System { content, .. }) if content == "compact_boundary"));
+
+    for line in [init, tool_result, error_result, unknown] {
+        let message: CliMessage = serde_json::from_str(line).unwrap();
+        let original: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(serde_json::to_value(&message).unwrap(), original, "{}", line);
+    }
+}
+
+#[test]
+fn test_unknown_fields_round_trip() {
+    use clau::{ClaudeCliResponse, CliMessage, ContentBlock};
+
+    let lines = [
+        r#"{"type":"system","subtype":"init","session_id":"abc","uuid":"u1","output_style":"default","mcp_servers":[{"name":"docs","status":"connected","transport":"stdio"}]}"#,
+        r#"{"type":"assistant","message":{"id":"msg_1","type":"message","role":"assistant","content":[{"type":"text","text":"Hi","citations":[]},{"type":"tool_use","id":"toolu_1","name":"Read","input":{},"cache_control":{"type":"ephemeral"}}],"stop_sequence":"END","usage":{"input_tokens":1,"output_tokens":2,"cache_creation_input_tokens":0,"cache_read_input_tokens":5,"server_tool_use":{"web_search_requests":0},"service_tier":"standard"}},"session_id":"abc","uuid":"u2"}"#,
+        r#"{"type":"result","subtype":"success","is_error":false,"num_turns":1,"result":"Hi","session_id":"abc","permission_denials":[],"uuid":"u3"}"#,
+    ];
+    for line in lines {
+        let message: CliMessage = serde_json::from_str(line).unwrap();
+        assert!(!matches!(message, CliMessage::Other(_)), "{}", line);
+        let original: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(serde_json::to_value(&message).unwrap(), original, "{}", line);
+    }
+
+    let CliMessage::Assistant(assistant) = serde_json::from_str(lines[1]).unwrap() else {
+        panic!("expected an assistant message");
+    };
+    assert_eq!(assistant.extra["uuid"], "u2");
+    assert_eq!(assistant.message.extra["stop_sequence"], "END");
+    assert_eq!(assistant.message.usage.unwrap().extra["server_tool_use"]["web_search_requests"], 0);
+    assert!(matches!(&assistant.message.content[0], ContentBlock::Text { extra, .. } if extra.contains_key("citations")));
+
+    // The simplified view keeps them too
+    for (line, uuid) in lines.iter().zip(["u1", "u2", "u3"]) {
+        let message: CliMessage = serde_json::from_str(line).unwrap();
+        assert_eq!(message.to_message().unwrap().meta().extra["uuid"], uuid, "{}", line);
+    }
+
+    let json = r#"{"type":"result","subtype":"success","cost_usd":0.1,"is_error":false,"duration_ms":10,"duration_api_ms":8,"num_turns":1,"result":"ok","total_cost":0.1,"session_id":"abc","usage":{"input_tokens":1},"permission_denials":[]}"#;
+    let message = r#"{"type":"assistant","content":"Hi","session_id":"abc","timestamp":null,"cost_usd":null,"duration_ms":null,"tokens_used":null,"uuid":"u4"}"#;
+    let parsed: clau::Message = serde_json::from_str(message).unwrap();
+    assert_eq!(parsed.meta().extra["uuid"], "u4");
+    let original: serde_json::Value = serde_json::from_str(message).unwrap();
+    assert_eq!(serde_json::to_value(&parsed).unwrap(), original);
+
+    let response: ClaudeCliResponse = serde_json::from_str(json).unwrap();
+    assert_eq!(response.extra["permission_denials"], serde_json::json!([]));
+    let original: serde_json::Value = serde_json::from_str(json).unwrap();
+    assert_eq!(serde_json::to_value(&response).unwrap(), original);
+}
+
+#[test]
+fn test_result_json_schema_drift() {
+    use clau::{ClaudeCliResponse, ClaudeResponse, ResultSchema};
+
+    let v1 = serde_json::json!({"type": "result", "subtype": "success", "cost_usd": 0.01, "is_error": false, "duration_ms": 10, "duration_api_ms": 8, "num_turns": 1, "result": "ok", "total_cost": 0.01, "session_id": "abc"});
+    let v2 = serde_json::json!({"type": "result", "subtype": "success", "is_error": false, "duration_ms": 10, "num_turns": 1, "result": "ok", "session_id": "abc", "total_cost_usd": 0.02});
+    let unknown = serde_json::json!({"type": "result", "result": "ok", "session_id": "abc", "cost": {"usd": 0.03}});
+
+    for (json, schema, cost) in [(v1, ResultSchema::V1, Some(0.01)), (v2, ResultSchema::V2, Some(0.02)), (unknown, ResultSchema::Unknown, None)] {
+        let response: ClaudeCliResponse = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(response.schema(), schema);
+        assert_eq!(response.cost(), cost);
+        assert_eq!(response.result, "ok");
+        assert!(response.error().is_none());
+
+        let metadata = ClaudeResponse::with_json(response.result, json).metadata.unwrap();
+        assert_eq!(metadata.schema, Some(schema));
+        assert_eq!(metadata.cost_usd, cost);
+    }
+}
+
+#[test]
+fn test_token_usage() {
+    use clau::{ClaudeResponse, Message, TokenUsage};
+    use clau_runtime::stream::MessageParser;
+
+    let usage = serde_json::json!({"input_tokens": 4, "cache_creation_input_tokens": 600, "cache_read_input_tokens": 2000, "output_tokens": 50, "service_tier": "standard"});
+    let result = serde_json::json!({"type": "result", "subtype": "success", "result": "ok", "session_id": "abc", "total_cost_usd": 0.01, "usage": usage});
+
+    let metadata = ClaudeResponse::with_json("ok".to_string(), result.clone()).metadata.unwrap();
+    let tokens = metadata.tokens_used.unwrap();
+    assert_eq!(tokens.cache_read_input_tokens, Some(2000));
+    assert_eq!(tokens.service_tier.as_deref(), Some("standard"));
+    assert_eq!(tokens.total_input(), 2604);
+    assert_eq!(tokens.total(), 2654);
+
+    let parser = MessageParser::new(clau::StreamFormat::StreamJson);
+    match parser.parse_line(&result.to_string()).unwrap() {
+        Some(Message::Result { meta, stats, .. }) => {
+            assert_eq!(stats.total_tokens, tokens);
+            assert_eq!(meta.tokens_used.as_ref(), Some(&tokens));
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    let sum: TokenUsage = [tokens.clone(), TokenUsage::new(1, 2)].iter().sum();
+    assert_eq!(sum.input_tokens, Some(5));
+    assert_eq!(sum.output_tokens, Some(52));
+    assert_eq!(sum.cache_read_input_tokens, Some(2000));
+    assert_eq!(sum.service_tier.as_deref(), Some("standard"));
+    assert_eq!(TokenUsage::default() + TokenUsage::default(), TokenUsage::default());
+}
+
+#[test]
+fn test_stream_json_metadata_uses_result_message() {
+    use clau::ClaudeResponse;
+
+    let raw = serde_json::json!([
+        {"type": "system", "subtype": "init", "session_id": "first"},
+        {"type": "assistant", "session_id": "first", "message": {"content": []}},
+        {"type": "result", "subtype": "success", "session_id": "latest", "duration_ms": 10}
+    ]);
+
+    let response = ClaudeResponse::with_json("hi".to_string(), raw);
+    let metadata = response.metadata.expect("metadata");
+    assert_eq!(metadata.session_id, "latest");
+    assert_eq!(metadata.duration_ms, Some(10));
+}
+
+#[test]
+fn test_config_overrides_merge() {
+    use clau::ConfigOverrides;
+
+    let base = Config::builder()
+        .model("claude-sonnet-4-20250514")
+        .system_prompt("base prompt")
+        .timeout_secs(30)
+        .build();
+
+    let overrides = ConfigOverrides {
+        model: Some("claude-opus-4-20250514".to_string()),
+        stream_format: Some(StreamFormat::StreamJson),
+        ..Default::default()
+    };
+
+    let merged = base.with_overrides(&overrides);
+    assert_eq!(merged.model, Some("claude-opus-4-20250514".to_string()));
+    assert_eq!(merged.stream_format, StreamFormat::StreamJson);
+    assert_eq!(merged.system_prompt, Some("base prompt".to_string()));
+    assert_eq!(merged.timeout_secs, Some(30));
+}
+
+#[tokio::test]
+async fn test_scripted_transport_json_response() {
+    use clau::Client;
+    use clau::ScriptedTransport;
+
+    let transport = ScriptedTransport::new().reply(
+        r#"{"type":"result","subtype":"success","cost_usd":0.002,"is_error":false,"duration_ms":900,"duration_api_ms":800,"num_turns":1,"result":"4","total_cost":0.002,"session_id":"s-1"}"#,
+    );
+    let client = Client::builder()
+        .stream_format(StreamFormat::Json)
+        .model("claude-sonnet-4-20250514")
+        .transport(transport.clone())
+        .build();
+
+    let response = client.query("What is 2 + 2?").send_full().await.unwrap();
+    assert_eq!(response.content, "4");
+    assert_eq!(response.metadata.unwrap().session_id, "s-1");
+
+    let calls = transport.calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].query, "What is 2 + 2?");
+    assert_eq!(calls[0].config.model.as_deref(), Some("claude-sonnet-4-20250514"));
+}
+
+#[tokio::test]
+async fn test_scripted_transport_stream() {
+    use clau::{Client, Message};
+    use clau::ScriptedTransport;
+    use futures::StreamExt;
+
+    let transport = ScriptedTransport::new().reply(concat!(
+        r#"{"type":"system","subtype":"init","session_id":"s-2"}"#, "\n",
+        r#"{"type":"assistant","session_id":"s-2","message":{"content":[{"type":"text","text":"Hi"}]}}"#, "\n",
+        r#"{"type":"result","subtype":"success","session_id":"s-2","num_turns":1}"#, "\n",
+    ));
+    let client = Client::builder().transport(transport).build();
+
+    let messages: Vec<_> = client.query("Hello").stream().await.unwrap().collect().await;
+    assert_eq!(messages.len(), 3);
+    assert!(matches!(messages[1], Ok(Message::Assistant { ref content, .. }) if content == "Hi"));
+}
+
+#[tokio::test]
+async fn test_cassette_replay() {
+    use clau::{CassetteTransport, Client};
+    use clau_runtime::cassette::{Cassette, Interaction};
+    use clau_runtime::process::build_args;
+
+    let config = Config::builder().stream_format(StreamFormat::Json).build();
+    let cassette = Cassette {
+        interactions: vec![Interaction {
+            args: build_args(&config),
+            query: "Hello".to_string(),
+            stdout: vec![
+                r#"{"type":"result","subtype":"success","cost_usd":0.001,"is_error":false,"duration_ms":500,"duration_api_ms":400,"num_turns":1,"result":"Hi there","total_cost":0.001,"session_id":"s-3"}"#.to_string(),
+            ],
+            stderr: String::new(),
+            exit_code: Some(0),
+        }],
+    };
+
+    let path = std::env::temp_dir().join(format!("clau-cassette-{}.json", std::process::id()));
+    cassette.save(&path).unwrap();
+
+    let client = Client::builder()
+        .config(config)
+        .transport(CassetteTransport::replay(&path).unwrap())
+        .build();
+
+    assert_eq!(client.send("Hello").await.unwrap(), "Hi there");
+    assert!(client.send("Something else").await.is_err());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_cassette_replay_stream() {
+    use clau::{CassetteTransport, Client, Message};
+    use clau_runtime::cassette::{Cassette, Interaction};
+    use clau_runtime::process::build_args;
+    use futures::StreamExt;
+
+    let config = Config::builder().stream_format(StreamFormat::StreamJson).build();
+    let cassette = Cassette {
+        interactions: vec![Interaction {
+            args: build_args(&config),
+            query: "Hello".to_string(),
+            stdout: vec![
+                r#"{"type":"system","subtype":"init","session_id":"s-4"}"#.to_string(),
+                r#"{"type":"assistant","message":{"role":"assistant","content":[{"type":"text","text":"Hi there"}]},"session_id":"s-4"}"#.to_string(),
+                r#"{"type":"result","subtype":"success","is_error":false,"num_turns":1,"result":"Hi there","session_id":"s-4","total_cost_usd":0.001}"#.to_string(),
+            ],
+            stderr: String::new(),
+            exit_code: Some(0),
+        }],
+    };
+
+    let path = std::env::temp_dir().join(format!("clau-cassette-stream-{}.json", std::process::id()));
+    cassette.save(&path).unwrap();
+
+    let client = Client::builder()
+        .config(config)
+        .transport(CassetteTransport::replay(&path).unwrap())
+        .build();
+
+    let messages: Vec<Message> = client.query("Hello").stream().await.unwrap().map(|m| m.unwrap()).collect().await;
+    assert!(matches!(messages[0], Message::Init { ref meta } if meta.session_id == "s-4"));
+    assert!(matches!(messages[1], Message::Assistant { ref content, .. } if content == "Hi there"));
+    assert!(matches!(messages[2], Message::Result { failure: None, .. }));
+    assert_eq!(messages.len(), 3);
+    assert_eq!(client.send("Hello").await.unwrap(), "Hi there");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_claude_bin_env_must_exist() {
+    use clau::{Config, Error};
+    use clau_runtime::discovery::{find_claude_binary, CLAUDE_BIN_ENV};
+
+    // No other test in this binary resolves the executable through the environment
+    let missing = std::env::temp_dir().join("clau-no-such-claude");
+    std::env::set_var(CLAUDE_BIN_ENV, &missing);
+    let result = find_claude_binary(&Config::default());
+    std::env::remove_var(CLAUDE_BIN_ENV);
+
+    match result {
+        Err(Error::BinaryNotFound { searched }) => assert_eq!(searched, [missing]),
+        other => panic!("expected BinaryNotFound, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_cli_version_parse() {
+    use clau::CliVersion;
+
+    assert_eq!(CliVersion::parse("1.0.17 (Claude Code)"), Some(CliVersion::new(1, 0, 17)));
+    assert_eq!(CliVersion::parse("2.1.0-beta.3\n"), Some(CliVersion::new(2, 1, 0)));
+    assert_eq!(CliVersion::parse("claude 0.9"), Some(CliVersion::new(0, 9, 0)));
+    assert_eq!(CliVersion::parse("unknown"), None);
+    assert!(CliVersion::new(1, 0, 17) < CliVersion::new(1, 1, 0));
+}
+
+#[test]
+fn test_capabilities_from_help() {
+    use clau::{Capabilities, CliVersion, Error};
+
+    let help = "Usage: claude [options]\n\n  -p, --print            Print response\n  --model <model>        Model\n  --allowedTools, --allowed-tools <tools...>\n";
+    let capabilities = Capabilities::from_help(Some(CliVersion::new(1, 0, 0)), help);
+    assert!(capabilities.supports("--model"));
+    assert!(capabilities.supports("--allowedTools"));
+    assert!(!capabilities.supports("--max-tokens"));
+
+    let config = Config::builder().model("claude-sonnet-4-20250514").build();
+    assert!(capabilities.check(&config).is_ok());
+
+    let config = Config::builder().max_tokens(100).build();
+    let err = capabilities.check(&config).unwrap_err();
+    assert!(matches!(err, Error::ConfigError(ref msg) if msg.contains("max_tokens")));
+
+    // Nothing known means nothing is rejected
+    assert!(Capabilities::unknown().check(&config).is_ok());
+}
+
+#[test]
+fn test_error_classification() {
+    use clau::{ClaudeCliResponse, Error};
+
+    let err = Error::classify("Error: Invalid API key · Please run /login").unwrap();
+    assert!(matches!(err, Error::AuthenticationFailed(_)));
+    assert!(!err.is_retryable());
+
+    let err = Error::classify("API Error: 529 {\"type\":\"overloaded_error\"}").unwrap();
+    assert!(matches!(err, Error::Overloaded(_)));
+    assert!(err.is_retryable());
+
+    let err = Error::classify("Prompt is too long").unwrap();
+    assert!(matches!(err, Error::ContextTooLong(_)));
+
+    assert!(Error::classify("segfault").is_none());
+
+    let response: ClaudeCliResponse = serde_json::from_value(serde_json::json!({
+        "type": "result",
+        "subtype": "error_during_execution",
+        "cost_usd": 0.0,
+        "is_error": true,
+        "duration_ms": 10,
+        "duration_api_ms": null,
+        "num_turns": 2,
+        "result": "",
+        "total_cost": 0.0,
+        "session_id": "abc"
+    }))
+    .unwrap();
+    match response.error() {
+        Some(Error::ExecutionError(failure)) => {
+            assert_eq!(failure.to_string(), "error_during_execution");
+            assert_eq!((failure.num_turns, failure.cost_usd), (2, Some(0.0)));
+            assert_eq!(failure.session_id, "abc");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let success = serde_json::json!({"type": "result", "subtype": "success", "is_error": false, "result": "ok"});
+    assert!(Error::from_result(&success).is_none());
+}
+
+#[test]
+fn test_process_failure_context() {
+    use clau::{Error, ProcessFailure};
+
+    let stderr = format!("{}\nfatal: boom", "noise ".repeat(2000));
+    let failure = ProcessFailure::new("claude -p --model m <prompt>", None, Some(9), &stderr, "{\"type\":\"system\"}");
+    assert!(failure.was_signaled());
+    assert!(failure.stderr.len() <= ProcessFailure::OUTPUT_LIMIT);
+    assert!(failure.stderr.ends_with("fatal: boom"));
+
+    let err = Error::process_failed(failure);
+    let message = err.to_string();
+    assert!(message.contains("killed by signal 9"), "{}", message);
+    assert!(message.contains("claude -p --model m <prompt>"), "{}", message);
+    assert!(matches!(err, Error::ProcessFailed(ref f) if f.stdout.contains("system")));
+}
+
+#[test]
+fn test_shell_escape() {
+    use clau_runtime::command::shell_escape;
+
+    assert_eq!(shell_escape("--model"), "--model");
+    assert_eq!(shell_escape(""), "''");
+    assert_eq!(shell_escape("two words"), "'two words'");
+    assert_eq!(shell_escape("it's $HOME"), r"'it'\''s $HOME'");
+}
+
+#[tokio::test]
+async fn test_retry_policy_retries_transient_errors() {
+    use clau::{Client, Error, RetryPolicy};
+    use clau::ScriptedTransport;
+    use std::time::Duration;
+
+    let policy = RetryPolicy::new(3).backoff(Duration::from_millis(1), Duration::from_millis(20));
+    let transport = ScriptedTransport::new()
+        .fail(Error::Overloaded("busy".to_string()))
+        .fail(Error::RateLimited { retry_after: Some(Duration::from_millis(10)) })
+        .reply(r#"{"type":"result","subtype":"success","cost_usd":0.0,"is_error":false,"duration_ms":1,"duration_api_ms":1,"num_turns":1,"result":"ok","total_cost":0.0,"session_id":"s-1"}"#);
+    let client = Client::builder()
+        .stream_format(StreamFormat::Json)
+        .retry_policy(policy.clone())
+        .transport(transport.clone())
+        .build();
+
+    let response = client.send_full("Hi").await.unwrap();
+    assert_eq!(response.content, "ok");
+    assert_eq!(response.metadata.unwrap().attempts, 3);
+    assert_eq!(transport.calls().len(), 3);
+
+    // Permanent failures are returned straight away
+    let transport = ScriptedTransport::new()
+        .fail(Error::AuthenticationFailed("Invalid API key".to_string()))
+        .reply("unused");
+    let client = Client::builder()
+        .retry_policy(policy)
+        .transport(transport.clone())
+        .build();
+    assert!(matches!(client.send("Hi").await, Err(Error::AuthenticationFailed(_))));
+    assert_eq!(transport.calls().len(), 1);
+
+    // Text responses have no metadata but still report their attempts
+    let transport = ScriptedTransport::new()
+        .fail(Error::Overloaded("busy".to_string()))
+        .reply("ok");
+    let client = Client::builder()
+        .retry_policy(RetryPolicy::new(2).backoff(Duration::from_millis(1), Duration::from_millis(5)))
+        .transport(transport)
+        .build();
+    let response = client.send_full("Hi").await.unwrap();
+    assert_eq!((response.content.as_str(), response.attempts), ("ok", 2));
+}
+
+#[test]
+fn test_retry_policy_delay() {
+    use clau::{Error, RetryPolicy};
+    use std::time::Duration;
+
+    let policy = RetryPolicy::new(5)
+        .backoff(Duration::from_millis(100), Duration::from_millis(1000))
+        .jitter(0.5);
+    let overloaded = Error::Overloaded(String::new());
+
+    for attempt in 1..=5 {
+        let ceiling = (100u64 << (attempt - 1)).min(1000);
+        let delay = policy.delay(attempt, &overloaded).as_millis() as u64;
+        assert!(delay <= ceiling && delay >= ceiling / 2, "attempt {}: {}ms", attempt, delay);
+    }
+    assert!(policy.should_retry(4, &overloaded));
+    assert!(!policy.should_retry(5, &overloaded));
+
+    let rate_limited = Error::RateLimited { retry_after: Some(Duration::from_millis(800)) };
+    assert_eq!(policy.delay(1, &rate_limited), Duration::from_millis(800));
+    assert!(policy.should_retry(1, &rate_limited));
+    assert!(policy.clone().respect_retry_after(false).delay(1, &rate_limited) <= Duration::from_millis(100));
+    
+    // A limit that resets later than the longest backoff is not waited out
+    let usage_limit = Error::RateLimited { retry_after: Some(Duration::from_secs(5 * 3600)) };
+    assert!(!policy.should_retry(1, &usage_limit));
+    assert_eq!(policy.delay(1, &usage_limit), Duration::from_millis(1000));
+    assert!(policy.clone().respect_retry_after(false).should_retry(1, &usage_limit));
+    
+    // A timed-out run may have used tools already, so it is only retried on request
+    let timeout = Error::Timeout(30);
+    assert!(!timeout.is_retryable());
+    assert!(!policy.should_retry(1, &timeout));
+    assert!(policy.clone().retry_timeouts(true).should_retry(1, &timeout));
+}
+
+#[tokio::test]
+async fn test_fair_queuing_rotates_callers() {
+    use clau::CancellationToken;
+    use clau_runtime::concurrency::ConcurrencyLimiter;
+    use std::sync::{Arc, Mutex};
+
+    let limiter = Arc::new(ConcurrencyLimiter::new(1, true));
+    let cancel = CancellationToken::new();
+    let order = Arc::new(Mutex::new(Vec::new()));
+
+    let first = limiter.acquire("batch", &cancel).await.unwrap();
+    let mut waiters = Vec::new();
+    for (caller, name) in [("batch", "b1"), ("batch", "b2"), ("batch", "b3"), ("ui", "u1")] {
+        let (limiter, cancel, order) = (limiter.clone(), cancel.clone(), order.clone());
+        waiters.push(tokio::spawn(async move {
+            let _permit = limiter.acquire(caller, &cancel).await.unwrap();
+            order.lock().unwrap().push(name);
+        }));
+        tokio::task::yield_now().await;
+    }
+
+    let metrics = limiter.metrics();
+    assert_eq!((metrics.running, metrics.queued), (1, 4));
+    assert_eq!(metrics.queued_by_caller.get("batch"), Some(&3));
+
+    drop(first);
+    for waiter in waiters {
+        waiter.await.unwrap();
+    }
+
+    // The interactive caller only waits for one batch query, not all three
+    assert_eq!(*order.lock().unwrap(), ["b1", "u1", "b2", "b3"]);
+    let metrics = limiter.metrics();
+    assert_eq!((metrics.running, metrics.queued, metrics.total_started), (0, 0, 5));
+}
+
+#[tokio::test]
+async fn test_cancelled_waiter_leaves_queue() {
+    use clau::{CancellationToken, Error};
+    use clau_runtime::concurrency::ConcurrencyLimiter;
+    use std::sync::Arc;
+
+    let limiter = Arc::new(ConcurrencyLimiter::new(1, false));
+    let held = limiter.acquire("a", &CancellationToken::new()).await.unwrap();
+
+    let cancel = CancellationToken::new();
+    let waiter = {
+        let (limiter, cancel) = (limiter.clone(), cancel.clone());
+        tokio::spawn(async move { limiter.acquire("b", &cancel).await })
+    };
+    tokio::task::yield_now().await;
+    assert_eq!(limiter.metrics().queued, 1);
+
+    cancel.cancel();
+    assert!(matches!(waiter.await.unwrap(), Err(Error::Cancelled)));
+    assert_eq!(limiter.metrics().queued, 0);
+
+    drop(held);
+    assert_eq!(limiter.metrics().running, 0);
+}